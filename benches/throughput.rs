@@ -0,0 +1,63 @@
+use clayton_cpu::Register::{V0, V1, V2, V3, V4, V5};
+use clayton_cpu::{bytes_to_opcode, decode, Assembler, CPU, PROGRAM_START};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+const INSTRUCTIONS: usize = 10_000;
+
+// an endless arithmetic loop that never touches the display or the keypad
+fn arithmetic_loop() -> Vec<u8> {
+    Assembler::new()
+        .ld(V1, 0x01)
+        .ld(V2, 0x03)
+        .label("loop")
+        .add_xy(V0, V1)
+        .xor_xy(V3, V0)
+        .shr_xy(V4, V3)
+        .mul_xy(V2, V1)
+        .sub_xy(V5, V2)
+        .jmp_label("loop")
+        .assemble()
+        .unwrap()
+}
+
+fn decode_only(c: &mut Criterion) {
+    let rom = arithmetic_loop();
+    let opcodes: Vec<u16> = rom
+        .chunks_exact(2)
+        .map(|pair| bytes_to_opcode(pair[0], pair[1]))
+        .collect();
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(INSTRUCTIONS as u64));
+    group.bench_function("arithmetic_loop", |b| {
+        b.iter(|| {
+            for &opcode in opcodes.iter().cycle().take(INSTRUCTIONS) {
+                black_box(decode(black_box(opcode)));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn execute(c: &mut Criterion) {
+    let rom = arithmetic_loop();
+
+    let mut group = c.benchmark_group("execute");
+    group.throughput(Throughput::Elements(INSTRUCTIONS as u64));
+    group.bench_function("arithmetic_loop", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut cpu = CPU::new_with_seed(0);
+                cpu.set_standard_mode(false);
+                cpu.load_program(&rom, PROGRAM_START).unwrap();
+                cpu
+            },
+            |cpu| cpu.run_with_limit(INSTRUCTIONS).unwrap(),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode_only, execute);
+criterion_main!(benches);
@@ -0,0 +1,3958 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crossterm::{
+    cursor::MoveTo,
+    event::{self, Event, KeyCode},
+    queue,
+    style::{Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{Clear, ClearType},
+};
+// RenderConfig takes crossterm colors, re-exported so callers don't need the crate
+#[cfg(feature = "std")]
+pub use crossterm::style::Color;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{self, Write},
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Instant,
+};
+
+// 0x000..0x200 is the interpreter area on the VIP, programs start right after it
+pub const PROGRAM_START: usize = 0x200;
+pub const FONT_START: usize = 0x050;
+pub const MEMORY_SIZE: usize = 0x1000;
+
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// fast_forward ticks the timers once per this many steps, about 600 instructions
+// per second against the 60Hz timers
+const STEPS_PER_TIMER_TICK: usize = 10;
+
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// SUPER-CHIP 8x10 digits, loaded right after the small font
+const LARGE_FONT: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    DivideByZero,
+    StackOverflow,
+    StackUnderflow,
+    UnknownOpcode(u16),
+    ProgramTooLarge(usize),
+    PcOutOfBounds(usize),
+    InvalidSaveState,
+    BreakpointHit(usize),
+    MisalignedPc(usize),
+    Watchpoint { location: Watch, old: u8, new: u8 },
+    SegmentOverlap(usize),
+    FontOverlap(usize),
+    InvalidRegister(u8),
+    AddressOutOfBounds(usize),
+    ForbiddenOpcode(u16),
+    // both fonts have to fit below MEMORY_SIZE, this is the base that didn't
+    FontOutOfRange(usize),
+    MemoryTooSmall(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    Register(Register),
+    Memory(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum Register {
+    V0,
+    V1,
+    V2,
+    V3,
+    V4,
+    V5,
+    V6,
+    V7,
+    V8,
+    V9,
+    VA,
+    VB,
+    VC,
+    VD,
+    VE,
+    VF,
+}
+
+impl Register {
+    pub const ALL: [Register; 16] = [
+        Register::V0,
+        Register::V1,
+        Register::V2,
+        Register::V3,
+        Register::V4,
+        Register::V5,
+        Register::V6,
+        Register::V7,
+        Register::V8,
+        Register::V9,
+        Register::VA,
+        Register::VB,
+        Register::VC,
+        Register::VD,
+        Register::VE,
+        Register::VF,
+    ];
+
+    // for nibbles decoded out of an opcode, which are always in range
+    fn from_nibble(x: u8) -> Register {
+        Register::ALL[(x & 0xF) as usize]
+    }
+}
+
+impl TryFrom<u8> for Register {
+    type Error = CpuError;
+
+    fn try_from(x: u8) -> Result<Register, CpuError> {
+        Register::ALL
+            .get(x as usize)
+            .copied()
+            .ok_or(CpuError::InvalidRegister(x))
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "V{:X}", *self as u8)
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::DivideByZero => write!(f, "division by zero is not allowed"),
+            CpuError::StackOverflow => write!(f, "stack overflow"),
+            CpuError::StackUnderflow => write!(f, "stack underflow"),
+            CpuError::UnknownOpcode(opcode) => write!(f, "unknown opcode {:04x}", opcode),
+            CpuError::ProgramTooLarge(overflow) => {
+                write!(f, "program overflows memory by {} bytes", overflow)
+            }
+            CpuError::PcOutOfBounds(addr) => {
+                write!(f, "program counter {:#06x} is outside of memory", addr)
+            }
+            CpuError::InvalidSaveState => write!(f, "save state is corrupt or incompatible"),
+            CpuError::BreakpointHit(addr) => write!(f, "breakpoint hit at {:#06x}", addr),
+            CpuError::MisalignedPc(addr) => {
+                write!(f, "program counter {:#06x} is not 2-byte aligned", addr)
+            }
+            CpuError::Watchpoint { location, old, new } => match location {
+                Watch::Register(x) => write!(f, "{} changed {:02x} -> {:02x}", x, old, new),
+                Watch::Memory(addr) => {
+                    write!(f, "memory {:#06x} changed {:02x} -> {:02x}", addr, old, new)
+                }
+            },
+            CpuError::SegmentOverlap(addr) => write!(f, "segments overlap at {:#06x}", addr),
+            CpuError::FontOverlap(addr) => {
+                write!(f, "load at {:#06x} overwrites the reserved font area", addr)
+            }
+            CpuError::InvalidRegister(x) => write!(f, "there is no register V{}", x),
+            CpuError::ForbiddenOpcode(opcode) => {
+                write!(f, "opcode {:04x} is not allowed in this sandbox", opcode)
+            }
+            CpuError::AddressOutOfBounds(addr) => {
+                write!(f, "address {:#06x} is outside of memory", addr)
+            }
+            CpuError::FontOutOfRange(base) => {
+                write!(
+                    f,
+                    "a font at {:#06x} doesn't fit below {:#06x}",
+                    base, MEMORY_SIZE
+                )
+            }
+            CpuError::MemoryTooSmall(size) => write!(
+                f,
+                "{} bytes of memory can't hold the interpreter area, at least {} are needed",
+                size, MEMORY_SIZE
+            ),
+        }
+    }
+}
+
+impl core::error::Error for CpuError {}
+
+// keeping both fonts inside the first 4KB means I can always address them,
+// whatever the memory size or the index_12bit quirk
+fn check_font_base(base: usize) -> Result<(), CpuError> {
+    match base.checked_add(FONT.len() + LARGE_FONT.len()) {
+        Some(end) if end <= MEMORY_SIZE => Ok(()),
+        _ => Err(CpuError::FontOutOfRange(base)),
+    }
+}
+
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+}
+
+// hashes a running counter with a randomly keyed SipHash; the keys come from the OS
+#[cfg(feature = "std")]
+pub struct OsRng {
+    state: RandomState,
+    counter: u64,
+}
+
+#[cfg(feature = "std")]
+impl OsRng {
+    pub fn new() -> OsRng {
+        OsRng {
+            state: RandomState::new(),
+            counter: 0,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for OsRng {
+    fn default() -> OsRng {
+        OsRng::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl RandomSource for OsRng {
+    fn next_byte(&mut self) -> u8 {
+        self.counter = self.counter.wrapping_add(1);
+
+        let mut hasher = self.state.build_hasher();
+        hasher.write_u64(self.counter);
+        hasher.finish() as u8
+    }
+}
+
+// xorshift64, the same seed always yields the same Cxkk results
+pub struct SeededRng {
+    seed: u64,
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> SeededRng {
+        // xorshift never leaves the all-zero state
+        let state = if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        };
+        SeededRng { seed, state }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RandomSource for SeededRng {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 32) as u8
+    }
+}
+
+#[cfg(feature = "std")]
+fn default_rng() -> Box<dyn RandomSource> {
+    Box::new(OsRng::new())
+}
+
+// without an OS to seed from, CPU::new falls back to a fixed seed
+#[cfg(not(feature = "std"))]
+fn default_rng() -> Box<dyn RandomSource> {
+    Box::new(SeededRng::new(0x2545_F491_4F6C_DD1D))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    CosmacVip,
+    SuperChip,
+    Modern,
+    XoChip,
+}
+
+impl Profile {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Profile::CosmacVip => Quirks {
+                index_overflow_flag: false,
+                shift_quirk: false,
+                load_store_quirk: true,
+                jump_quirk: false,
+                index_12bit: true,
+                wrap_sprites: true,
+                clip_sprite_y: false,
+                long_index: false,
+                bit_planes: false,
+                vf_collision_mode: CollisionMode::Boolean,
+                display_wait: true,
+                logic_quirk_reset_vf: true,
+            },
+            Profile::SuperChip => Quirks {
+                index_overflow_flag: false,
+                shift_quirk: true,
+                load_store_quirk: false,
+                jump_quirk: true,
+                index_12bit: false,
+                wrap_sprites: false,
+                clip_sprite_y: true,
+                long_index: false,
+                bit_planes: false,
+                vf_collision_mode: CollisionMode::RowCount,
+                display_wait: false,
+                logic_quirk_reset_vf: false,
+            },
+            Profile::Modern => Quirks {
+                index_overflow_flag: false,
+                shift_quirk: false,
+                load_store_quirk: true,
+                jump_quirk: false,
+                index_12bit: false,
+                wrap_sprites: false,
+                clip_sprite_y: true,
+                long_index: false,
+                bit_planes: false,
+                vf_collision_mode: CollisionMode::Boolean,
+                display_wait: false,
+                logic_quirk_reset_vf: false,
+            },
+            Profile::XoChip => Quirks {
+                index_overflow_flag: false,
+                shift_quirk: false,
+                load_store_quirk: true,
+                jump_quirk: false,
+                index_12bit: false,
+                wrap_sprites: true,
+                clip_sprite_y: false,
+                long_index: true,
+                bit_planes: true,
+                vf_collision_mode: CollisionMode::Boolean,
+                display_wait: false,
+                logic_quirk_reset_vf: false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // set VF when Fx1E pushes I past 0x0FFF (Spacefight 2091! relies on this)
+    pub index_overflow_flag: bool,
+    // false: 8xy6/8xyE shift Vy and store the result in Vx (COSMAC VIP)
+    // true: shift Vx in place and ignore Vy (SUPER-CHIP)
+    pub shift_quirk: bool,
+    // true: Fx55/Fx65 leave I pointing past the last register touched (I += x + 1)
+    // false: I is left unchanged
+    pub load_store_quirk: bool,
+    // false: Bnnn jumps to nnn + V0
+    // true: Bxnn jumps to xnn + Vx (SUPER-CHIP)
+    pub jump_quirk: bool,
+    // true: I is a 12-bit register and wraps at 0x0FFF like on the VIP
+    // false: I free-runs over the full u16 range (SUPER-CHIP)
+    pub index_12bit: bool,
+    // true: sprite pixels past the right edge wrap to the left side
+    // false: they are clipped. the sprite origin itself always wraps
+    pub wrap_sprites: bool,
+    // true: sprite rows past the bottom edge are dropped and can't collide
+    // false: they wrap to the top
+    pub clip_sprite_y: bool,
+    // true: F000 NNNN loads a full 16-bit address into I (XO-CHIP)
+    // false: F000 is an unknown opcode
+    pub long_index: bool,
+    // true: two bit-planes for 4 colors, selected with Fx01 (XO-CHIP)
+    // false: a single monochrome plane and Fx01 is an unknown opcode
+    pub bit_planes: bool,
+    // what Dxyn leaves in VF, see CollisionMode
+    pub vf_collision_mode: CollisionMode,
+    // true: Dxyn waits for the vertical blank, step reports it with
+    // StepOutcome::AwaitingVBlank (COSMAC VIP)
+    // false: draws don't wait
+    pub display_wait: bool,
+    // true: 8xy1/8xy2/8xy3 clear VF after the result is written (COSMAC VIP)
+    // false: they leave VF alone
+    pub logic_quirk_reset_vf: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionMode {
+    // VF = 1 if any pixel was turned off
+    Boolean,
+    // SUPER-CHIP hi-res: VF = rows that collided plus rows clipped off the bottom.
+    // lo-res draws still report a boolean
+    RowCount,
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Profile::Modern.quirks()
+    }
+}
+
+// what 0x0000 does. CHIP-8 has no halt opcode and real ROMs contain zero words,
+// so the default treats it like any other ignored SYS call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HaltPolicy {
+    Halt,
+    #[default]
+    NoOp,
+    Error,
+}
+
+// CHIP-8 opcodes are canonically big-endian, high byte first. LittleEndian is an
+// interop escape hatch for tools that emit swapped words: load_program swaps
+// every word back so the fetch path never has to care
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    #[default]
+    BigEndian,
+    LittleEndian,
+}
+
+// which opcodes step may run, for sandboxing untrusted ROMs. everything is
+// allowed until a class or pattern is forbidden
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpcodeMask {
+    // one bit per high nibble
+    allowed_classes: u16,
+    // (mask, pattern) pairs like OpcodeInfo, matching any of them forbids the opcode
+    forbidden_patterns: Vec<(u16, u16)>,
+}
+
+impl Default for OpcodeMask {
+    fn default() -> OpcodeMask {
+        OpcodeMask::allow_all()
+    }
+}
+
+impl OpcodeMask {
+    pub fn allow_all() -> OpcodeMask {
+        OpcodeMask {
+            allowed_classes: 0xFFFF,
+            forbidden_patterns: Vec::new(),
+        }
+    }
+
+    // whitelist: only opcodes whose high nibble is in `classes`
+    pub fn only_classes(classes: &[u8]) -> OpcodeMask {
+        let allowed_classes = classes
+            .iter()
+            .fold(0, |bits, &class| bits | 1 << (class & 0xF));
+
+        OpcodeMask {
+            allowed_classes,
+            forbidden_patterns: Vec::new(),
+        }
+    }
+
+    pub fn forbid_class(mut self, class: u8) -> OpcodeMask {
+        self.allowed_classes &= !(1 << (class & 0xF));
+        self
+    }
+
+    // e.g. forbid_pattern(0xF00F, 0x800D) for DIV
+    pub fn forbid_pattern(mut self, mask: u16, pattern: u16) -> OpcodeMask {
+        self.forbidden_patterns.push((mask, pattern));
+        self
+    }
+
+    pub fn allows(&self, opcode: u16) -> bool {
+        self.allowed_classes & (1 << (opcode >> 12)) != 0
+            && !self
+                .forbidden_patterns
+                .iter()
+                .any(|&(mask, pattern)| opcode & mask == pattern)
+    }
+}
+
+// what an opcode this interpreter doesn't implement does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownPolicy {
+    // fail the step with CpuError::UnknownOpcode
+    #[default]
+    Trap,
+    // log it and carry on with the next instruction
+    Skip,
+    // log it and stop the run loops cleanly
+    Halt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Continue,
+    // a draw ran under the display_wait quirk, the next step belongs to the next
+    // 60Hz frame
+    AwaitingVBlank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Halt,
+    Nop,
+    ScrollDown(u8),
+    Cls,
+    Ret,
+    ScrollRight,
+    ScrollLeft,
+    Lores,
+    Hires,
+    Sys(u16),
+    Jmp(u16),
+    Call(u16),
+    Se { x: u8, kk: u8 },
+    Sne { x: u8, kk: u8 },
+    SeXy { x: u8, y: u8 },
+    Ld { x: u8, kk: u8 },
+    LdXy { x: u8, y: u8 },
+    AddXy { x: u8, y: u8 },
+    SubXy { x: u8, y: u8 },
+    SubnXy { x: u8, y: u8 },
+    AndXy { x: u8, y: u8 },
+    OrXy { x: u8, y: u8 },
+    XorXy { x: u8, y: u8 },
+    ShrXy { x: u8, y: u8 },
+    ShlXy { x: u8, y: u8 },
+    MulXy { x: u8, y: u8 },
+    DivXy { x: u8, y: u8 },
+    SneXy { x: u8, y: u8 },
+    LdI(u16),
+    // F000 NNNN, the address lives in the following word
+    LdILong,
+    Plane(u8),
+    JmpV0 { x: u8, addr: u16 },
+    Rnd { x: u8, kk: u8 },
+    Draw { x: u8, y: u8, n: u8 },
+    SkipIfKey(u8),
+    SkipIfNotKey(u8),
+    LdVxDt(u8),
+    ReadKey(u8),
+    LdDtVx(u8),
+    LdStVx(u8),
+    AddIVx(u8),
+    LdF(u8),
+    LdHf(u8),
+    Bcd(u8),
+    StoreRegs(u8),
+    LoadRegs(u8),
+    Unknown(u16),
+}
+
+// one differing location between two CPUs, `old` is self and `new` is the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateDiff {
+    Register {
+        index: u8,
+        old: u8,
+        new: u8,
+    },
+    Memory {
+        addr: usize,
+        old: u8,
+        new: u8,
+    },
+    Stack {
+        slot: usize,
+        old: u16,
+        new: u16,
+    },
+    Pc {
+        old: usize,
+        new: usize,
+    },
+    StackPointer {
+        old: usize,
+        new: usize,
+    },
+    Index {
+        old: u16,
+        new: u16,
+    },
+    DelayTimer {
+        old: u8,
+        new: u8,
+    },
+    SoundTimer {
+        old: u8,
+        new: u8,
+    },
+    Pixel {
+        plane: usize,
+        x: usize,
+        y: usize,
+        old: bool,
+        new: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuEvent {
+    PixelChanged { x: usize, y: usize, on: bool },
+    DisplayCleared,
+    DisplayScrolled,
+    RegisterWrite { index: u8, value: u8 },
+    SoundStart,
+    SoundStop,
+}
+
+pub trait Clock {
+    fn elapsed(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+#[cfg(feature = "std")]
+pub struct SystemClock {
+    start: Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+pub trait AudioSink {
+    fn set_playing(&mut self, playing: bool);
+}
+
+pub struct NullAudio;
+
+impl AudioSink for NullAudio {
+    fn set_playing(&mut self, _playing: bool) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: u8,
+    pub pressed: bool,
+}
+
+// a fake peripheral mapped over single memory addresses. Fx55, Fx65, Fx33 and the
+// sprite fetch of Dxyn go to the device instead of RAM
+pub trait MmioDevice {
+    fn read(&self, addr: usize) -> u8;
+    fn write(&mut self, addr: usize, value: u8);
+}
+
+pub trait InputSource {
+    // called before every instruction until it returns None, `cycle` is the
+    // number of instructions executed so far
+    fn poll(&mut self, cycle: u64) -> Option<KeyEvent>;
+}
+
+// replays (cycle, key, pressed) entries, which must be sorted by cycle
+pub struct RecordedInput {
+    events: Vec<(u64, u8, bool)>,
+    next: usize,
+}
+
+impl RecordedInput {
+    pub fn new(events: Vec<(u64, u8, bool)>) -> RecordedInput {
+        RecordedInput { events, next: 0 }
+    }
+}
+
+impl InputSource for RecordedInput {
+    fn poll(&mut self, cycle: u64) -> Option<KeyEvent> {
+        let &(at, key, pressed) = self.events.get(self.next)?;
+
+        if at > cycle {
+            return None;
+        }
+
+        self.next += 1;
+        Some(KeyEvent { key, pressed })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputConfig {
+    // a held key is released once it hasn't been reported again for this long
+    pub release_timeout: Duration,
+}
+
+impl Default for InputConfig {
+    fn default() -> InputConfig {
+        // one 60Hz frame
+        InputConfig {
+            release_timeout: Duration::from_micros(16_667),
+        }
+    }
+}
+
+// terminals only report presses, so releases are synthesized from silence. a
+// press of a key that is already down is auto-repeat and only keeps it held.
+// timestamps are any monotonic time, e.g. Clock::elapsed
+pub struct KeyTracker {
+    config: InputConfig,
+    last_seen: [Option<Duration>; 16],
+}
+
+impl KeyTracker {
+    pub fn new(config: InputConfig) -> KeyTracker {
+        KeyTracker {
+            config,
+            last_seen: [None; 16],
+        }
+    }
+
+    pub fn press(&mut self, key: u8, now: Duration) -> Option<KeyEvent> {
+        let last_seen = self.last_seen.get_mut(key as usize)?;
+        let repeat = last_seen.is_some();
+
+        *last_seen = Some(now);
+        if repeat {
+            return None;
+        }
+
+        Some(KeyEvent { key, pressed: true })
+    }
+
+    // releases every key that timed out by `now`
+    pub fn expire(&mut self, now: Duration) -> Vec<KeyEvent> {
+        let timeout = self.config.release_timeout;
+        let mut released = Vec::new();
+
+        for (key, last_seen) in self.last_seen.iter_mut().enumerate() {
+            if let Some(at) = *last_seen {
+                if now.saturating_sub(at) >= timeout {
+                    *last_seen = None;
+                    released.push(KeyEvent {
+                        key: key as u8,
+                        pressed: false,
+                    });
+                }
+            }
+        }
+
+        released
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderConfig {
+    pub fg: Color,
+    pub bg: Color,
+    pub on_char: char,
+    pub off_char: char,
+    // XO-CHIP: up to four colors indexed by pixel_color. lit pixels past the end
+    // fall back to fg and unlit ones to bg
+    pub palette: Vec<Color>,
+}
+
+#[cfg(feature = "std")]
+impl Default for RenderConfig {
+    fn default() -> RenderConfig {
+        RenderConfig {
+            fg: Color::White,
+            bg: Color::Black,
+            on_char: '█',
+            off_char: ' ',
+            palette: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl RenderConfig {
+    fn color(&self, pixel: u8) -> Color {
+        match self.palette.get(pixel as usize) {
+            Some(&color) => color,
+            None if pixel == 0 => self.bg,
+            None => self.fg,
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+pub struct BeepAudio {
+    _stream: rodio::OutputStream,
+    sink: rodio::Sink,
+}
+
+#[cfg(feature = "audio")]
+impl BeepAudio {
+    pub fn new() -> Result<BeepAudio, Box<dyn std::error::Error>> {
+        let (stream, handle) = rodio::OutputStream::try_default()?;
+        let sink = rodio::Sink::try_new(&handle)?;
+
+        sink.append(rodio::source::SineWave::new(440.0));
+        sink.pause();
+
+        Ok(BeepAudio {
+            _stream: stream,
+            sink,
+        })
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioSink for BeepAudio {
+    fn set_playing(&mut self, playing: bool) {
+        if playing {
+            self.sink.play();
+        } else {
+            self.sink.pause();
+        }
+    }
+}
+
+// sized for SUPER-CHIP hi-res, lo-res only uses the top-left 64x32 corner
+type Plane = [[bool; HIRES_WIDTH]; HIRES_HEIGHT];
+
+const BLANK_PLANE: Plane = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+
+// `row` is the sprite row the pixel came from, `x` and `y` are screen coordinates
+struct SpritePixel {
+    plane: usize,
+    row: usize,
+    x: usize,
+    y: usize,
+}
+
+// a single monochrome plane unless the bit_planes quirk asks for the XO-CHIP
+// pair, whose bits combine into a 2-bit color per pixel. both variants live
+// inline so the monochrome path never goes through a pointer
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Screen {
+    Mono(Plane),
+    Color([Plane; 2]),
+}
+
+impl Screen {
+    fn new(bit_planes: bool) -> Screen {
+        if bit_planes {
+            Screen::Color([BLANK_PLANE; 2])
+        } else {
+            Screen::Mono(BLANK_PLANE)
+        }
+    }
+
+    fn planes(&self) -> &[Plane] {
+        match self {
+            Screen::Mono(plane) => core::slice::from_ref(plane),
+            Screen::Color(planes) => planes,
+        }
+    }
+
+    fn planes_mut(&mut self) -> &mut [Plane] {
+        match self {
+            Screen::Mono(plane) => core::slice::from_mut(plane),
+            Screen::Color(planes) => planes,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Serialize, Deserialize)]
+struct CpuState {
+    registers: [u8; 16],
+    position_in_memory: usize,
+    memory: Vec<u8>,
+    stack: Vec<u16>,
+    stack_pointer: usize,
+    index: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    display: Vec<Vec<Vec<bool>>>,
+    plane_mask: u8,
+    hires: bool,
+    keys: [bool; 16],
+    key_latch: Option<u8>,
+    halted: bool,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct CPU {
+    registers: [u8; 16],
+    position_in_memory: usize,
+    start_address: usize,
+    memory: Vec<u8>,
+    stack: Vec<u16>,
+    stack_pointer: usize,
+    index: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    screen: Screen,
+    // (x, y, width, height) bounding every pixel changed since take_dirty_rect
+    dirty_rect: Option<(usize, usize, usize, usize)>,
+    // planes touched by draw, cls and the scrolls, one bit per plane (XO-CHIP Fx01)
+    plane_mask: u8,
+    hires: bool,
+    keys: [bool; 16],
+    // the key a pending Fx0A saw go down, written to Vx once it comes back up
+    key_latch: Option<u8>,
+    halted: bool,
+    // set by request_halt, turned into `halted` at the top of the run loops
+    halt_requested: bool,
+    on_zero_opcode: HaltPolicy,
+    on_unknown: UnknownPolicy,
+    rom_byte_order: ByteOrder,
+    allowed: OpcodeMask,
+    breakpoints: BTreeSet<usize>,
+    // lets execution resume past the breakpoint that just stopped it
+    skip_breakpoint: bool,
+    watched_registers: BTreeSet<u8>,
+    watched_memory: BTreeSet<usize>,
+    // first watched write of the current step, reported once the step completes
+    watch_hit: Option<(Watch, u8, u8)>,
+    quirks: Quirks,
+    font_base: usize,
+    // true: Fx0A blocks on the terminal until a key is typed (std builds only)
+    // false (default): Fx0A re-executes every step until a key set through
+    // set_key is released again
+    blocking_key_wait: bool,
+    // reject odd program counters, which usually means a jump into data
+    strict_alignment: bool,
+    // true: the MUL (8xyC) and DIV (8xyD) extensions of this emulator are unknown
+    // opcodes, like on every real interpreter
+    standard_mode: bool,
+    // refuse loads into the font area instead of only warning about them
+    protect_font: bool,
+    // wrap jump and call targets past the end of memory instead of failing
+    wrap_jumps: bool,
+    rng: Box<dyn RandomSource>,
+    seed: Option<u64>,
+    trace: Option<Box<dyn FnMut(usize, u16)>>,
+    // every range load_program wrote, code and data alike
+    loaded: Vec<(usize, usize)>,
+    // a write landed in `loaded`, so a disassembly of the ROM may be stale
+    self_modified: bool,
+    on_self_modify: Option<Box<dyn FnMut(usize, u8)>>,
+    audio: Box<dyn AudioSink>,
+    input: Option<Box<dyn InputSource>>,
+    io_hooks: BTreeMap<usize, Box<dyn MmioDevice>>,
+    sound_playing: bool,
+    #[cfg(feature = "std")]
+    events: Option<Sender<CpuEvent>>,
+    #[cfg(feature = "std")]
+    trace_log: Option<Box<dyn Write>>,
+    cycles: u64,
+    // Dxyn draws that turned a pixel off, and the screen origin of the latest one
+    collisions: u64,
+    last_collision: Option<(usize, usize)>,
+    // executed instructions keyed by the opcode's high nibble
+    opcode_stats: BTreeMap<u8, u64>,
+    // every distinct opcode executed since the last clear_coverage
+    coverage: BTreeSet<u16>,
+}
+
+impl fmt::Debug for CPU {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CPU")
+            .field("registers", &self.registers)
+            .field("position_in_memory", &self.position_in_memory)
+            .field("memory", &self.memory)
+            .field("stack", &self.stack)
+            .field("stack_pointer", &self.stack_pointer)
+            .field("index", &self.index)
+            .field("delay_timer", &self.delay_timer)
+            .field("sound_timer", &self.sound_timer)
+            .field("screen", &self.screen)
+            .field("dirty_rect", &self.dirty_rect)
+            .field("plane_mask", &self.plane_mask)
+            .field("hires", &self.hires)
+            .field("keys", &self.keys)
+            .field("key_latch", &self.key_latch)
+            .field("halted", &self.halted)
+            .field("on_zero_opcode", &self.on_zero_opcode)
+            .field("on_unknown", &self.on_unknown)
+            .field("rom_byte_order", &self.rom_byte_order)
+            .field("allowed", &self.allowed)
+            .field("breakpoints", &self.breakpoints)
+            .field("watched_registers", &self.watched_registers)
+            .field("watched_memory", &self.watched_memory)
+            .field("quirks", &self.quirks)
+            .field("font_base", &self.font_base)
+            .field("seed", &self.seed)
+            .field("cycles", &self.cycles)
+            .field("collisions", &self.collisions)
+            .field("blocking_key_wait", &self.blocking_key_wait)
+            .field("strict_alignment", &self.strict_alignment)
+            .field("standard_mode", &self.standard_mode)
+            .field("protect_font", &self.protect_font)
+            .field("io_hooks", &self.io_hooks.keys().collect::<Vec<_>>())
+            .field("wrap_jumps", &self.wrap_jumps)
+            .field("start_address", &self.start_address)
+            .field("self_modified", &self.self_modified)
+            .finish_non_exhaustive()
+    }
+}
+
+// one-line summary for trace logs, Debug keeps the full dump
+impl fmt::Display for CPU {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PC={:#05x} I={:#05x} SP={}",
+            self.position_in_memory, self.index, self.stack_pointer
+        )?;
+        for (i, value) in self.registers.iter().enumerate() {
+            write!(f, " V{:X}={:02X}", i, value)?;
+        }
+        write!(f, " DT={} ST={}", self.delay_timer, self.sound_timer)
+    }
+}
+
+impl Default for CPU {
+    fn default() -> CPU {
+        CPU::new()
+    }
+}
+
+// every constructor on CPU is a shortcut for one of these
+pub struct CpuBuilder {
+    quirks: Quirks,
+    memory_size: usize,
+    stack_depth: usize,
+    rng: Option<Box<dyn RandomSource>>,
+    seed: Option<u64>,
+    font_base: usize,
+    audio: Option<Box<dyn AudioSink>>,
+    start_address: usize,
+}
+
+impl Default for CpuBuilder {
+    fn default() -> CpuBuilder {
+        CpuBuilder {
+            quirks: Quirks::default(),
+            memory_size: MEMORY_SIZE,
+            stack_depth: 16,
+            rng: None,
+            seed: None,
+            font_base: FONT_START,
+            audio: None,
+            start_address: PROGRAM_START,
+        }
+    }
+}
+
+impl CpuBuilder {
+    pub fn new() -> CpuBuilder {
+        CpuBuilder::default()
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> CpuBuilder {
+        self.quirks = quirks;
+        self
+    }
+
+    // at least MEMORY_SIZE, build panics otherwise and try_build returns the error
+    pub fn memory_size(mut self, size: usize) -> CpuBuilder {
+        self.memory_size = size;
+        self
+    }
+
+    // SUPER-CHIP interpreters commonly allow nesting deeper than the VIP's 16 levels
+    pub fn stack_depth(mut self, depth: usize) -> CpuBuilder {
+        self.stack_depth = depth;
+        self
+    }
+
+    // takes precedence over seed
+    pub fn rng(mut self, rng: Box<dyn RandomSource>) -> CpuBuilder {
+        self.rng = Some(rng);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> CpuBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    // the fonts take 180 bytes and must end below MEMORY_SIZE
+    pub fn font_base(mut self, base: usize) -> CpuBuilder {
+        self.font_base = base;
+        self
+    }
+
+    pub fn audio_sink(mut self, audio: Box<dyn AudioSink>) -> CpuBuilder {
+        self.audio = Some(audio);
+        self
+    }
+
+    // where PC starts and where reset puts it back, has to be inside memory
+    pub fn start_address(mut self, addr: usize) -> CpuBuilder {
+        self.start_address = addr;
+        self
+    }
+
+    // panics on anything try_build would refuse
+    pub fn build(self) -> CPU {
+        match self.try_build() {
+            Ok(cpu) => cpu,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    pub fn try_build(self) -> Result<CPU, CpuError> {
+        if self.memory_size < MEMORY_SIZE {
+            return Err(CpuError::MemoryTooSmall(self.memory_size));
+        }
+        if self.start_address >= self.memory_size {
+            return Err(CpuError::AddressOutOfBounds(self.start_address));
+        }
+        check_font_base(self.font_base)?;
+
+        Ok(CPU::from_builder(self))
+    }
+}
+
+impl CPU {
+    pub fn new() -> CPU {
+        CpuBuilder::new().build()
+    }
+
+    pub fn builder() -> CpuBuilder {
+        CpuBuilder::new()
+    }
+
+    pub fn new_with_rng(rng: Box<dyn RandomSource>) -> CPU {
+        CpuBuilder::new().rng(rng).build()
+    }
+
+    pub fn new_with_seed(seed: u64) -> CPU {
+        CpuBuilder::new().seed(seed).build()
+    }
+
+    // only known when the CPU was built from a seed
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> CPU {
+        CpuBuilder::new().quirks(quirks).build()
+    }
+
+    // fonts in place, ROM at PROGRAM_START and PC pointing at it, ready to run
+    pub fn boot(rom: &[u8]) -> Result<CPU, CpuError> {
+        let mut cpu = CPU::new();
+        cpu.load_program(rom, PROGRAM_START)?;
+        Ok(cpu)
+    }
+
+    pub fn with_memory_size(size: usize) -> CPU {
+        CpuBuilder::new().memory_size(size).build()
+    }
+
+    pub fn with_stack_depth(depth: usize) -> CPU {
+        CpuBuilder::new().stack_depth(depth).build()
+    }
+
+    // the builder has already been checked by try_build
+    fn from_builder(builder: CpuBuilder) -> CPU {
+        let (rng, seed) = match (builder.rng, builder.seed) {
+            (Some(rng), _) => (rng, None),
+            (None, Some(seed)) => (
+                Box::new(SeededRng::new(seed)) as Box<dyn RandomSource>,
+                Some(seed),
+            ),
+            (None, None) => (default_rng(), None),
+        };
+
+        let mut cpu = CPU {
+            registers: [0; 16],
+            memory: vec![0; builder.memory_size],
+            position_in_memory: builder.start_address,
+            start_address: builder.start_address,
+            stack: vec![0; builder.stack_depth],
+            stack_pointer: 0,
+            index: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            screen: Screen::new(builder.quirks.bit_planes),
+            dirty_rect: None,
+            plane_mask: 1,
+            hires: false,
+            keys: [false; 16],
+            key_latch: None,
+            halted: false,
+            halt_requested: false,
+            on_zero_opcode: HaltPolicy::default(),
+            on_unknown: UnknownPolicy::default(),
+            rom_byte_order: ByteOrder::default(),
+            allowed: OpcodeMask::default(),
+            breakpoints: BTreeSet::new(),
+            skip_breakpoint: false,
+            watched_registers: BTreeSet::new(),
+            watched_memory: BTreeSet::new(),
+            watch_hit: None,
+            quirks: builder.quirks,
+            font_base: builder.font_base,
+            blocking_key_wait: false,
+            strict_alignment: false,
+            standard_mode: true,
+            protect_font: false,
+            wrap_jumps: false,
+            rng,
+            seed,
+            trace: None,
+            loaded: Vec::new(),
+            self_modified: false,
+            on_self_modify: None,
+            audio: builder.audio.unwrap_or_else(|| Box::new(NullAudio)),
+            input: None,
+            io_hooks: BTreeMap::new(),
+            sound_playing: false,
+            #[cfg(feature = "std")]
+            events: None,
+            #[cfg(feature = "std")]
+            trace_log: None,
+            cycles: 0,
+            collisions: 0,
+            last_collision: None,
+            opcode_stats: BTreeMap::new(),
+            coverage: BTreeSet::new(),
+        };
+
+        cpu.load_font();
+        cpu
+    }
+
+    fn load_font(&mut self) {
+        let small = self.font_base;
+        let large = self.large_font_base();
+
+        self.memory[small..small + FONT.len()].copy_from_slice(&FONT);
+        self.memory[large..large + LARGE_FONT.len()].copy_from_slice(&LARGE_FONT);
+    }
+
+    fn large_font_base(&self) -> usize {
+        self.font_base + FONT.len()
+    }
+
+    // moves both fonts to `base`, the old copy is left behind in memory
+    pub fn set_font_base(&mut self, base: usize) -> Result<(), CpuError> {
+        check_font_base(base)?;
+        self.font_base = base;
+        self.load_font();
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.registers = [0; 16];
+        self.position_in_memory = self.start_address;
+        self.stack.fill(0);
+        self.stack_pointer = 0;
+        self.index = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.screen.planes_mut().fill(BLANK_PLANE);
+        self.mark_all_dirty();
+        self.plane_mask = 1;
+        self.hires = false;
+        self.keys = [false; 16];
+        self.key_latch = None;
+        self.halted = false;
+        self.halt_requested = false;
+        self.skip_breakpoint = false;
+        self.update_audio();
+        self.load_font();
+    }
+
+    pub fn reset_and_clear(&mut self) {
+        self.memory.fill(0);
+        self.loaded.clear();
+        self.self_modified = false;
+        self.reset();
+    }
+
+    pub fn with_trace(mut self, trace: impl FnMut(usize, u16) + 'static) -> CPU {
+        self.trace = Some(Box::new(trace));
+        self
+    }
+
+    // called with the address and value of every write into loaded ROM bytes
+    pub fn with_self_modify_hook(mut self, hook: impl FnMut(usize, u8) + 'static) -> CPU {
+        self.on_self_modify = Some(Box::new(hook));
+        self
+    }
+
+    pub fn with_audio_sink(mut self, audio: Box<dyn AudioSink>) -> CPU {
+        self.audio = audio;
+        self
+    }
+
+    // keys are driven by the source and Fx0A never blocks while one is attached
+    pub fn with_input(mut self, input: Box<dyn InputSource>) -> CPU {
+        self.input = Some(input);
+        self
+    }
+
+    // replaces any device already mapped at `addr`
+    pub fn attach_device(&mut self, addr: usize, device: Box<dyn MmioDevice>) {
+        self.io_hooks.insert(addr, device);
+    }
+
+    pub fn detach_device(&mut self, addr: usize) -> Option<Box<dyn MmioDevice>> {
+        self.io_hooks.remove(&addr)
+    }
+
+    // replaces any previous subscriber, only one receiver is attached at a time
+    #[cfg(feature = "std")]
+    pub fn events(&mut self) -> Receiver<CpuEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.events = Some(sender);
+        receiver
+    }
+
+    #[cfg(feature = "std")]
+    fn emit(&mut self, event: CpuEvent) {
+        if let Some(sender) = &self.events {
+            if sender.send(event).is_err() {
+                self.events = None;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn emit(&mut self, _event: CpuEvent) {}
+
+    // one line per executed instruction, written after it ran:
+    //   0x0200 6005 V0=05 V1=00 ... VF=00 I=0000 SP=0
+    // PC and opcode are those of the instruction, the registers, I (4 hex digits)
+    // and SP (decimal) are the state it left behind. hex is always lowercase and the
+    // format is kept stable for diffing against other emulators. a failed write
+    // detaches the writer
+    #[cfg(feature = "std")]
+    pub fn enable_trace_log(&mut self, writer: Box<dyn Write>) {
+        self.trace_log = Some(writer);
+    }
+
+    #[cfg(feature = "std")]
+    fn write_trace_log(&mut self, pc: usize, opcode: u16) {
+        let Some(writer) = self.trace_log.as_mut() else {
+            return;
+        };
+
+        let mut line = format!("{:#06x} {:04x}", pc, opcode);
+        for (i, value) in self.registers.iter().enumerate() {
+            line.push_str(&format!(" V{:X}={:02x}", i, value));
+        }
+        line.push_str(&format!(
+            " I={:04x} SP={}\n",
+            self.index, self.stack_pointer
+        ));
+
+        if let Err(err) = writer.write_all(line.as_bytes()) {
+            log::error!("trace log disabled: {}", err);
+            self.trace_log = None;
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn write_trace_log(&mut self, _pc: usize, _opcode: u16) {}
+
+    #[cfg(feature = "std")]
+    fn has_subscriber(&self) -> bool {
+        self.events.is_some()
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn has_subscriber(&self) -> bool {
+        false
+    }
+
+    pub fn load_program(&mut self, program: &[u8], start_address: usize) -> Result<(), CpuError> {
+        let end_address = start_address
+            .checked_add(program.len())
+            .ok_or(CpuError::AddressOutOfBounds(start_address))?;
+
+        if end_address > self.memory.len() {
+            return Err(CpuError::ProgramTooLarge(end_address - self.memory.len()));
+        }
+
+        self.check_font_overlap(start_address, program.len())?;
+
+        if !program.is_empty() {
+            self.loaded.push((start_address, end_address));
+        }
+
+        let target = &mut self.memory[start_address..end_address];
+        target.copy_from_slice(program);
+        // a trailing odd byte has no partner and is left as is
+        if self.rom_byte_order == ByteOrder::LittleEndian {
+            for word in target.chunks_exact_mut(2) {
+                word.swap(0, 1);
+            }
+        }
+        Ok(())
+    }
+
+    // 0x000 up to the end of the large font is reserved for the interpreter
+    fn check_font_overlap(&self, start_address: usize, len: usize) -> Result<(), CpuError> {
+        let font_end = self.large_font_base() + LARGE_FONT.len();
+
+        if len > 0 && start_address < font_end {
+            if self.protect_font {
+                return Err(CpuError::FontOverlap(start_address));
+            }
+            log::warn!(
+                "loading at {:#06x} overwrites the font area below {:#06x}",
+                start_address,
+                font_end
+            );
+        }
+        Ok(())
+    }
+
+    // every segment is checked before any of them is written
+    pub fn load_segments(&mut self, segments: &[(usize, &[u8])]) -> Result<(), CpuError> {
+        let mut ranges = segments
+            .iter()
+            .map(|&(start, bytes)| {
+                let end = start
+                    .checked_add(bytes.len())
+                    .ok_or(CpuError::AddressOutOfBounds(start))?;
+                Ok((start, end))
+            })
+            .collect::<Result<Vec<(usize, usize)>, CpuError>>()?;
+        ranges.sort_unstable();
+
+        for &(_, end) in &ranges {
+            if end > self.memory.len() {
+                return Err(CpuError::ProgramTooLarge(end - self.memory.len()));
+            }
+        }
+        for pair in ranges.windows(2) {
+            if pair[1].0 < pair[0].1 {
+                return Err(CpuError::SegmentOverlap(pair[1].0));
+            }
+        }
+        if self.protect_font {
+            for &(start, end) in &ranges {
+                self.check_font_overlap(start, end - start)?;
+            }
+        }
+
+        for &(start, bytes) in segments {
+            self.load_program(bytes, start)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_rom_from_file(&mut self, path: &Path, start_address: usize) -> io::Result<()> {
+        let rom = fs::read(path)?;
+
+        self.load_program(&rom, start_address)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let state = CpuState {
+            registers: self.registers,
+            position_in_memory: self.position_in_memory,
+            memory: self.memory.to_vec(),
+            stack: self.stack.clone(),
+            stack_pointer: self.stack_pointer,
+            index: self.index,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            display: self
+                .screen
+                .planes()
+                .iter()
+                .map(|plane| plane.iter().map(|row| row.to_vec()).collect())
+                .collect(),
+            plane_mask: self.plane_mask,
+            hires: self.hires,
+            keys: self.keys,
+            key_latch: self.key_latch,
+            halted: self.halted,
+        };
+
+        bincode::serialize(&state).expect("CpuState is always serializable")
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), CpuError> {
+        let state: CpuState =
+            bincode::deserialize(bytes).map_err(|_| CpuError::InvalidSaveState)?;
+
+        if state.memory.len() != self.memory.len()
+            || state.display.len() != self.screen.planes().len()
+            || state
+                .display
+                .iter()
+                .any(|plane| plane.len() != HIRES_HEIGHT)
+            || state
+                .display
+                .iter()
+                .flatten()
+                .any(|row| row.len() != HIRES_WIDTH)
+            || state.stack.len() != self.stack.len()
+            || state.stack_pointer > self.stack.len()
+            || state.position_in_memory >= self.memory.len()
+            || state
+                .stack
+                .iter()
+                .any(|&addr| addr as usize >= self.memory.len())
+            || state.key_latch.is_some_and(|key| key >= 16)
+        {
+            return Err(CpuError::InvalidSaveState);
+        }
+
+        self.registers = state.registers;
+        self.position_in_memory = state.position_in_memory;
+        self.memory.copy_from_slice(&state.memory);
+        self.stack.copy_from_slice(&state.stack);
+        self.stack_pointer = state.stack_pointer;
+        self.index = state.index;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        for (plane, saved) in self.screen.planes_mut().iter_mut().zip(&state.display) {
+            for (row, saved) in plane.iter_mut().zip(saved) {
+                row.copy_from_slice(saved);
+            }
+        }
+        self.plane_mask = state.plane_mask;
+        self.hires = state.hires;
+        self.mark_all_dirty();
+        self.keys = state.keys;
+        self.key_latch = state.key_latch;
+        self.halted = state.halted;
+        Ok(())
+    }
+
+    fn read_opcode(&self) -> Result<u16, CpuError> {
+        let p = self.position_in_memory;
+
+        if p + 1 >= self.memory.len() {
+            return Err(CpuError::PcOutOfBounds(p));
+        }
+        if self.strict_alignment && !p.is_multiple_of(2) {
+            return Err(CpuError::MisalignedPc(p));
+        }
+
+        Ok(bytes_to_opcode(self.memory[p], self.memory[p + 1]))
+    }
+
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        self.run_with_limit(usize::MAX)?;
+        Ok(())
+    }
+
+    // there is no clock here, so display waits are ignored
+    pub fn run_with_limit(&mut self, max_cycles: usize) -> Result<usize, CpuError> {
+        self.halted = false;
+
+        let mut cycles = 0;
+        while !self.should_stop() && cycles < max_cycles {
+            self.step()?;
+            cycles += 1;
+        }
+
+        Ok(cycles)
+    }
+
+    // checked before every step, so a predicate that already holds runs nothing.
+    // false means the cap or a halt came first
+    pub fn run_until<F: FnMut(&CPU) -> bool>(
+        &mut self,
+        mut pred: F,
+        max_cycles: usize,
+    ) -> Result<bool, CpuError> {
+        self.halted = false;
+
+        for _ in 0..max_cycles {
+            if pred(self) {
+                return Ok(true);
+            }
+            if self.should_stop() {
+                return Ok(false);
+            }
+            self.step()?;
+        }
+
+        Ok(pred(self))
+    }
+
+    // runs up to `cycles` steps with no side effects outside the CPU: the audio sink,
+    // the trace and the trace log are detached and Fx0A never blocks on the
+    // terminal. timers keep ticking so delay loops behave the same as in real time
+    pub fn fast_forward(&mut self, cycles: usize) -> Result<(), CpuError> {
+        let audio = core::mem::replace(&mut self.audio, Box::new(NullAudio));
+        let trace = self.trace.take();
+        #[cfg(feature = "std")]
+        let trace_log = self.trace_log.take();
+        let blocking_key_wait = self.blocking_key_wait;
+        let was_playing = self.sound_playing;
+
+        self.blocking_key_wait = false;
+        let result = self.fast_forward_steps(cycles);
+
+        self.audio = audio;
+        self.trace = trace;
+        #[cfg(feature = "std")]
+        {
+            self.trace_log = trace_log;
+        }
+        self.blocking_key_wait = blocking_key_wait;
+        // the sink missed every change while it was detached
+        if self.sound_playing != was_playing {
+            self.audio.set_playing(self.sound_playing);
+        }
+
+        result
+    }
+
+    fn fast_forward_steps(&mut self, cycles: usize) -> Result<(), CpuError> {
+        self.halted = false;
+
+        let mut since_tick = 0;
+        for _ in 0..cycles {
+            if self.should_stop() {
+                break;
+            }
+            let outcome = self.step()?;
+
+            // a display wait ends the frame early
+            since_tick += 1;
+            if since_tick == STEPS_PER_TIMER_TICK || outcome == StepOutcome::AwaitingVBlank {
+                self.tick_timers();
+                since_tick = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn run_realtime(&mut self, ips: u32) -> Result<(), CpuError> {
+        self.run_realtime_with_clock(ips, &mut SystemClock::new())
+    }
+
+    // instructions run at `ips` per second while timers always tick at 60Hz, both
+    // scheduled against the clock's elapsed time so neither drifts from the other
+    pub fn run_realtime_with_clock(
+        &mut self,
+        ips: u32,
+        clock: &mut dyn Clock,
+    ) -> Result<(), CpuError> {
+        const NANOS_PER_SEC: u128 = 1_000_000_000;
+        const TIMER_HZ: u128 = 60;
+
+        let ips = ips.max(1) as u128;
+        let start = clock.elapsed();
+        let mut steps: u128 = 0;
+        let mut ticks: u128 = 0;
+        // no steps run until `ticks` reaches this, set by a display wait
+        let mut vblank_tick: u128 = 0;
+
+        self.halted = false;
+        while !self.should_stop() {
+            let elapsed = (clock.elapsed() - start).as_nanos();
+
+            while ticks < elapsed * TIMER_HZ / NANOS_PER_SEC {
+                self.tick_timers();
+                ticks += 1;
+            }
+
+            let due = elapsed * ips / NANOS_PER_SEC;
+            if ticks < vblank_tick {
+                // steps that fall due during a display wait are dropped, not caught up on
+                steps = due;
+            } else if steps < due {
+                if self.step()? == StepOutcome::AwaitingVBlank {
+                    vblank_tick = ticks + 1;
+                }
+                steps += 1;
+                continue;
+            }
+
+            // rounded up, a deadline that falls short of its step or tick would
+            // sleep for 0 and spin
+            let next_step = ((steps + 1) * NANOS_PER_SEC).div_ceil(ips);
+            let next_tick = ((ticks + 1) * NANOS_PER_SEC).div_ceil(TIMER_HZ);
+            let wait = next_step.min(next_tick).saturating_sub(elapsed);
+            clock.sleep(Duration::from_nanos(wait as u64));
+        }
+
+        Ok(())
+    }
+
+    pub fn dump_registers(&self) -> String {
+        let mut out = String::new();
+
+        for (i, value) in self.registers.iter().enumerate() {
+            let sep = if i % 8 == 7 { '\n' } else { ' ' };
+            out.push_str(&format!("V{:X}={:02x}{}", i, value, sep));
+        }
+
+        out.push_str(&format!(
+            "I={:04x} PC={:04x} SP={:02x} DT={:02x} ST={:02x}",
+            self.index,
+            self.position_in_memory,
+            self.stack_pointer,
+            self.delay_timer,
+            self.sound_timer
+        ));
+        out
+    }
+
+    pub fn dump_memory(&self, start: usize, len: usize) -> String {
+        let start = start.min(self.memory.len());
+        let end = start.saturating_add(len).min(self.memory.len());
+        let mut out = String::new();
+
+        for (row, chunk) in self.memory[start..end].chunks(16).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            out.push_str(&format!(
+                "{:04x}: {:<47} |{}|\n",
+                start + row * 16,
+                hex.join(" "),
+                ascii
+            ));
+        }
+
+        out
+    }
+
+    pub fn pc(&self) -> usize {
+        self.position_in_memory
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn register(&self, x: Register) -> u8 {
+        self.registers[x as usize]
+    }
+
+    pub fn set_register(&mut self, x: Register, value: u8) {
+        self.registers[x as usize] = value;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // peek and poke go straight to RAM: attached devices and watchpoints don't see them
+    pub fn read_memory(&self, addr: usize) -> Result<u8, CpuError> {
+        self.memory
+            .get(addr)
+            .copied()
+            .ok_or(CpuError::AddressOutOfBounds(addr))
+    }
+
+    pub fn write_memory(&mut self, addr: usize, value: u8) -> Result<(), CpuError> {
+        let byte = self
+            .memory
+            .get_mut(addr)
+            .ok_or(CpuError::AddressOutOfBounds(addr))?;
+        *byte = value;
+        Ok(())
+    }
+
+    // only the active return addresses, innermost call last
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer]
+    }
+
+    pub fn self_modified(&self) -> bool {
+        self.self_modified
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn request_halt(&mut self) {
+        self.halt_requested = true;
+    }
+
+    // consumes a pending request_halt, true once execution should stop
+    fn should_stop(&mut self) -> bool {
+        if self.halt_requested {
+            self.halt_requested = false;
+            self.halted = true;
+        }
+        self.halted
+    }
+
+    pub fn set_zero_opcode_policy(&mut self, policy: HaltPolicy) {
+        self.on_zero_opcode = policy;
+    }
+
+    pub fn set_unknown_opcode_policy(&mut self, policy: UnknownPolicy) {
+        self.on_unknown = policy;
+    }
+
+    // checked by step before an opcode runs
+    pub fn set_allowed_opcodes(&mut self, allowed: OpcodeMask) {
+        self.allowed = allowed;
+    }
+
+    // applies to every later load_program, load_segments and load_rom_from_file
+    pub fn set_rom_byte_order(&mut self, order: ByteOrder) {
+        self.rom_byte_order = order;
+    }
+
+    pub fn set_blocking_key_wait(&mut self, blocking: bool) {
+        self.blocking_key_wait = blocking;
+    }
+
+    pub fn set_protect_font(&mut self, protect: bool) {
+        self.protect_font = protect;
+    }
+
+    pub fn set_strict_alignment(&mut self, strict: bool) {
+        self.strict_alignment = strict;
+    }
+
+    pub fn set_wrap_jumps(&mut self, wrap: bool) {
+        self.wrap_jumps = wrap;
+    }
+
+    // false turns MUL and DIV back on
+    pub fn set_standard_mode(&mut self, standard: bool) {
+        self.standard_mode = standard;
+    }
+
+    pub fn release_keys(&mut self) {
+        self.keys = [false; 16];
+    }
+
+    // the returned Vec only allocates once a difference is found. memory is
+    // compared over the shorter of the two sizes
+    pub fn diff(&self, other: &CPU) -> Vec<StateDiff> {
+        let mut diffs = Vec::new();
+
+        if self.position_in_memory != other.position_in_memory {
+            diffs.push(StateDiff::Pc {
+                old: self.position_in_memory,
+                new: other.position_in_memory,
+            });
+        }
+        if self.stack_pointer != other.stack_pointer {
+            diffs.push(StateDiff::StackPointer {
+                old: self.stack_pointer,
+                new: other.stack_pointer,
+            });
+        }
+        if self.index != other.index {
+            diffs.push(StateDiff::Index {
+                old: self.index,
+                new: other.index,
+            });
+        }
+        if self.delay_timer != other.delay_timer {
+            diffs.push(StateDiff::DelayTimer {
+                old: self.delay_timer,
+                new: other.delay_timer,
+            });
+        }
+        if self.sound_timer != other.sound_timer {
+            diffs.push(StateDiff::SoundTimer {
+                old: self.sound_timer,
+                new: other.sound_timer,
+            });
+        }
+
+        for (index, (&old, &new)) in self.registers.iter().zip(&other.registers).enumerate() {
+            if old != new {
+                diffs.push(StateDiff::Register {
+                    index: index as u8,
+                    old,
+                    new,
+                });
+            }
+        }
+        for (slot, (&old, &new)) in self.stack.iter().zip(&other.stack).enumerate() {
+            if old != new {
+                diffs.push(StateDiff::Stack { slot, old, new });
+            }
+        }
+        for (addr, (&old, &new)) in self.memory.iter().zip(&other.memory).enumerate() {
+            if old != new {
+                diffs.push(StateDiff::Memory { addr, old, new });
+            }
+        }
+        let planes = self.screen.planes().iter().zip(other.screen.planes());
+        for (plane, (rows, other_rows)) in planes.enumerate() {
+            for (y, (row, other_row)) in rows.iter().zip(other_rows).enumerate() {
+                for (x, (&old, &new)) in row.iter().zip(other_row).enumerate() {
+                    if old != new {
+                        diffs.push(StateDiff::Pixel {
+                            plane,
+                            x,
+                            y,
+                            old,
+                            new,
+                        });
+                    }
+                }
+            }
+        }
+
+        diffs
+    }
+
+    pub fn cycle_count(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn opcode_stats(&self) -> &BTreeMap<u8, u64> {
+        &self.opcode_stats
+    }
+
+    // draws that left VF nonzero, with the sprite origin of the latest one
+    pub fn collision_count(&self) -> u64 {
+        self.collisions
+    }
+
+    pub fn last_collision(&self) -> Option<(usize, usize)> {
+        self.last_collision
+    }
+
+    pub fn executed_opcodes(&self) -> impl Iterator<Item = u16> + '_ {
+        self.coverage.iter().copied()
+    }
+
+    pub fn clear_coverage(&mut self) {
+        self.coverage.clear();
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn watch_register(&mut self, x: Register) {
+        self.watched_registers.insert(x as u8);
+    }
+
+    pub fn unwatch_register(&mut self, x: Register) {
+        self.watched_registers.remove(&(x as u8));
+    }
+
+    pub fn watch_memory(&mut self, addr: usize) {
+        self.watched_memory.insert(addr);
+    }
+
+    pub fn unwatch_memory(&mut self, addr: usize) {
+        self.watched_memory.remove(&addr);
+    }
+
+    fn record_watch(&mut self, location: Watch, old: u8, new: u8) {
+        if old != new && self.watch_hit.is_none() {
+            self.watch_hit = Some((location, old, new));
+        }
+    }
+
+    pub fn step(&mut self) -> Result<StepOutcome, CpuError> {
+        let pc = self.position_in_memory;
+
+        if !self.skip_breakpoint && self.breakpoints.contains(&pc) {
+            self.skip_breakpoint = true;
+            return Err(CpuError::BreakpointHit(pc));
+        }
+        self.skip_breakpoint = false;
+        self.watch_hit = None;
+
+        if let Some(input) = self.input.as_mut() {
+            while let Some(event) = input.poll(self.cycles) {
+                self.keys[(event.key & 0xF) as usize] = event.pressed;
+            }
+        }
+
+        let opcode = self.read_opcode()?;
+
+        // refused before anything runs, so PC stays on the forbidden opcode
+        if !self.allowed.allows(opcode) {
+            return Err(CpuError::ForbiddenOpcode(opcode));
+        }
+
+        if let Some(trace) = self.trace.as_mut() {
+            trace(pc, opcode);
+        }
+
+        self.position_in_memory += 2;
+        self.cycles += 1;
+        *self.opcode_stats.entry((opcode >> 12) as u8).or_insert(0) += 1;
+        self.coverage.insert(opcode);
+
+        let registers_before = self.has_subscriber().then_some(self.registers);
+
+        let instruction = decode(opcode);
+        self.execute(instruction, pc)?;
+        self.write_trace_log(pc, opcode);
+
+        if let Some(before) = registers_before {
+            for (index, old) in before.into_iter().enumerate() {
+                let value = self.registers[index];
+
+                if value != old {
+                    self.emit(CpuEvent::RegisterWrite {
+                        index: index as u8,
+                        value,
+                    });
+                }
+            }
+        }
+
+        if let Some((location, old, new)) = self.watch_hit.take() {
+            return Err(CpuError::Watchpoint { location, old, new });
+        }
+
+        if self.quirks.display_wait && matches!(instruction, Instruction::Draw { .. }) {
+            return Ok(StepOutcome::AwaitingVBlank);
+        }
+
+        Ok(StepOutcome::Continue)
+    }
+
+    fn execute(&mut self, instruction: Instruction, pc: usize) -> Result<(), CpuError> {
+        match instruction {
+            Instruction::Halt => match self.on_zero_opcode {
+                HaltPolicy::Halt => {
+                    log::info!("terminating execution at {:#06x}", pc);
+                    self.halted = true;
+                }
+                HaltPolicy::NoOp => {}
+                HaltPolicy::Error => {
+                    log::warn!("zero opcode at {:#06x}", pc);
+                    return Err(CpuError::UnknownOpcode(0x0000));
+                }
+            },
+            Instruction::ScrollDown(n) => self.scroll_down(n),
+            Instruction::Cls => self.cls(),
+            Instruction::Ret => self.ret()?,
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Lores => self.set_hires(false),
+            Instruction::Hires => self.set_hires(true),
+            // machine code routines on the original hardware, ignored here
+            Instruction::Nop | Instruction::Sys(_) => {}
+            Instruction::Jmp(addr) => self.jmp(addr)?,
+            Instruction::Call(addr) => self.call(addr)?,
+            Instruction::Se { x, kk } => self.se(x, kk),
+            Instruction::Sne { x, kk } => self.sne(x, kk),
+            Instruction::SeXy { x, y } => self.se_xy(x, y),
+            Instruction::Ld { x, kk } => self.ld(x, kk),
+            Instruction::LdXy { x, y } => self.ld_xy(x, y),
+            Instruction::AddXy { x, y } => self.add_xy(x, y),
+            Instruction::SubXy { x, y } => self.sub_xy(x, y),
+            Instruction::SubnXy { x, y } => self.subn_xy(x, y),
+            Instruction::AndXy { x, y } => self.and_xy(x, y),
+            Instruction::OrXy { x, y } => self.or_xy(x, y),
+            Instruction::XorXy { x, y } => self.xor_xy(x, y),
+            Instruction::ShrXy { x, y } => self.shr_xy(x, y),
+            Instruction::ShlXy { x, y } => self.shl_xy(x, y),
+            // standard mode: the extensions decode to nothing a real interpreter runs
+            Instruction::MulXy { x, y } if self.standard_mode => {
+                self.unknown_opcode(0x800C | (x as u16) << 8 | (y as u16) << 4, pc)?
+            }
+            Instruction::DivXy { x, y } if self.standard_mode => {
+                self.unknown_opcode(0x800D | (x as u16) << 8 | (y as u16) << 4, pc)?
+            }
+            Instruction::MulXy { x, y } => self.mul_xy(x, y),
+            Instruction::DivXy { x, y } => self.div_xy(x, y)?,
+            Instruction::SneXy { x, y } => self.sne_xy(x, y),
+            Instruction::LdI(addr) => self.ld_i(addr),
+            Instruction::LdILong => self.ld_i_long(pc)?,
+            Instruction::Plane(x) => self.select_planes(x, pc)?,
+            Instruction::JmpV0 { x, addr } => self.jmp_v0(x, addr)?,
+            Instruction::Rnd { x, kk } => self.rnd(x, kk),
+            Instruction::Draw { x, y, n } => self.draw(x, y, n),
+            Instruction::SkipIfKey(x) => self.skip_if_key(x),
+            Instruction::SkipIfNotKey(x) => self.skip_if_not_key(x),
+            Instruction::LdVxDt(x) => self.ld_vx_dt(x),
+            Instruction::ReadKey(x) => self.read_key(x),
+            Instruction::LdDtVx(x) => self.ld_dt_vx(x),
+            Instruction::LdStVx(x) => self.ld_st_vx(x),
+            Instruction::AddIVx(x) => self.add_i_vx(x),
+            Instruction::LdF(x) => self.ld_f(x),
+            Instruction::LdHf(x) => self.ld_hf(x),
+            Instruction::Bcd(x) => self.bcd(x),
+            Instruction::StoreRegs(x) => self.store_regs(x),
+            Instruction::LoadRegs(x) => self.load_regs(x),
+            Instruction::Unknown(opcode) => self.unknown_opcode(opcode, pc)?,
+        }
+
+        Ok(())
+    }
+
+    // what step would refuse in this configuration, as (offset into `program`,
+    // opcode): unknown opcodes, MUL and DIV in standard mode, the XO-CHIP opcodes
+    // without their quirks and anything the opcode mask forbids. the same linear
+    // scan, with the same caveats, as the free validate
+    pub fn validate(&self, program: &[u8]) -> Vec<(usize, u16)> {
+        instructions(program, self.rom_byte_order)
+            .filter(|&(_, opcode, _)| !self.supports(opcode))
+            .map(|(offset, opcode, _)| (offset, opcode))
+            .collect()
+    }
+
+    fn supports(&self, opcode: u16) -> bool {
+        if !self.allowed.allows(opcode) {
+            return false;
+        }
+
+        match decode(opcode) {
+            Instruction::Halt => self.on_zero_opcode != HaltPolicy::Error,
+            Instruction::MulXy { .. } | Instruction::DivXy { .. } => !self.standard_mode,
+            Instruction::LdILong => self.quirks.long_index,
+            Instruction::Plane(_) => self.quirks.bit_planes,
+            Instruction::Unknown(_) => false,
+            _ => true,
+        }
+    }
+
+    fn unknown_opcode(&mut self, opcode: u16, pc: usize) -> Result<(), CpuError> {
+        match self.on_unknown {
+            UnknownPolicy::Trap => {
+                log::warn!("unknown opcode {:04x} at {:#06x}", opcode, pc);
+                return Err(CpuError::UnknownOpcode(opcode));
+            }
+            UnknownPolicy::Skip => {
+                log::warn!("skipping unknown opcode {:04x} at {:#06x}", opcode, pc);
+            }
+            UnknownPolicy::Halt => {
+                log::warn!("halting on unknown opcode {:04x} at {:#06x}", opcode, pc);
+                self.halted = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn display_size(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        }
+    }
+
+    // rows of plane 0 at the active resolution, each trimmed to the active width
+    pub fn framebuffer(&self) -> impl Iterator<Item = &[bool]> {
+        let (width, height) = self.display_size();
+        self.screen.planes()[0][..height]
+            .iter()
+            .map(move |row| &row[..width])
+    }
+
+    // lit on any plane
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixel_color(x, y) != 0
+    }
+
+    pub fn plane_pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        let (width, height) = self.display_size();
+
+        match self.screen.planes().get(plane) {
+            Some(rows) if x < width && y < height => rows[y][x],
+            _ => false,
+        }
+    }
+
+    // bit n is set when the pixel is lit on plane n, always 0 or 1 in monochrome
+    pub fn pixel_color(&self, x: usize, y: usize) -> u8 {
+        (0..self.screen.planes().len())
+            .filter(|&plane| self.plane_pixel(plane, x, y))
+            .fold(0, |color, plane| color | 1 << plane)
+    }
+
+    // clears the rect, so every call only reports what changed since the last one
+    pub fn take_dirty_rect(&mut self) -> Option<(usize, usize, usize, usize)> {
+        self.dirty_rect.take()
+    }
+
+    fn mark_dirty(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.dirty_rect = Some(match self.dirty_rect {
+            None => (x, y, width, height),
+            Some((dx, dy, dw, dh)) => {
+                let (left, top) = (dx.min(x), dy.min(y));
+                let right = (dx + dw).max(x + width);
+                let bottom = (dy + dh).max(y + height);
+                (left, top, right - left, bottom - top)
+            }
+        });
+    }
+
+    fn mark_all_dirty(&mut self) {
+        let (width, height) = self.display_size();
+        self.mark_dirty(0, 0, width, height);
+    }
+
+    fn plane_selected(&self, plane: usize) -> bool {
+        plane < self.screen.planes().len() && self.plane_mask & (1 << plane) != 0
+    }
+
+    #[cfg(feature = "std")]
+    pub fn render_terminal(&self, config: &RenderConfig) -> crossterm::Result<()> {
+        let mut stdout = io::stdout();
+        self.render_to(&mut stdout, config)?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    // the escape sequences render_terminal writes, one color switch per run of
+    // same-colored pixels
+    #[cfg(feature = "std")]
+    pub fn render_to(&self, out: &mut impl Write, config: &RenderConfig) -> crossterm::Result<()> {
+        let (width, height) = self.display_size();
+        queue!(
+            out,
+            SetBackgroundColor(config.bg),
+            MoveTo(0, 0),
+            Clear(ClearType::All)
+        )?;
+
+        self.render_rect_to(out, config, (0, 0, width, height))
+    }
+
+    // redraws only what changed since the last call. a full-screen rect clears
+    // first, since a resolution switch can leave stale cells outside the new size
+    #[cfg(feature = "std")]
+    pub fn render_terminal_dirty(&mut self, config: &RenderConfig) -> crossterm::Result<()> {
+        let mut stdout = io::stdout();
+        let (width, height) = self.display_size();
+
+        match self.take_dirty_rect() {
+            Some((0, 0, w, h)) if w >= width && h >= height => {
+                self.render_to(&mut stdout, config)?
+            }
+            Some(rect) => {
+                queue!(stdout, SetBackgroundColor(config.bg))?;
+                self.render_rect_to(&mut stdout, config, rect)?
+            }
+            None => return Ok(()),
+        }
+
+        stdout.flush()?;
+        Ok(())
+    }
+
+    // (x, y, width, height), clamped to the current display
+    #[cfg(feature = "std")]
+    pub fn render_rect_to(
+        &self,
+        out: &mut impl Write,
+        config: &RenderConfig,
+        rect: (usize, usize, usize, usize),
+    ) -> crossterm::Result<()> {
+        let (width, height) = self.display_size();
+        let (left, top) = (rect.0.min(width), rect.1.min(height));
+        let right = (rect.0 + rect.2).min(width);
+        let bottom = (rect.1 + rect.3).min(height);
+
+        for y in top..bottom {
+            queue!(out, MoveTo(left as u16, y as u16))?;
+
+            let mut run = String::new();
+            let mut run_color = None;
+            for x in left..right {
+                let pixel = self.pixel_color(x, y);
+                let color = config.color(pixel);
+
+                if run_color != Some(color) {
+                    if let Some(color) = run_color {
+                        queue!(out, SetForegroundColor(color), Print(&run))?;
+                        run.clear();
+                    }
+                    run_color = Some(color);
+                }
+                run.push(if pixel == 0 {
+                    config.off_char
+                } else {
+                    config.on_char
+                });
+            }
+            if let Some(color) = run_color {
+                queue!(out, SetForegroundColor(color), Print(&run))?;
+            }
+        }
+
+        queue!(out, ResetColor)?;
+        Ok(())
+    }
+
+    // rows packed MSB-first, each padded out to a whole byte
+    fn packed_rows(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        for row in self.framebuffer() {
+            for chunk in row.chunks(8) {
+                let mut byte = 0;
+                for (bit, &on) in chunk.iter().enumerate() {
+                    if on {
+                        byte |= 0x80 >> bit;
+                    }
+                }
+                out.push(byte);
+            }
+        }
+
+        out
+    }
+
+    // binary P4 bitmap, lit pixels are 1 so viewers draw them black on white
+    pub fn display_to_pbm(&self) -> Vec<u8> {
+        let (width, height) = self.display_size();
+        let mut out = format!("P4\n{} {}\n", width, height).into_bytes();
+
+        out.extend(self.packed_rows());
+        out
+    }
+
+    // 1-bit grayscale, lit pixels are white like on the terminal
+    #[cfg(feature = "png")]
+    pub fn display_to_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let (width, height) = self.display_size();
+        let mut out = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::One);
+        encoder
+            .write_header()?
+            .write_image_data(&self.packed_rows())?;
+
+        Ok(out)
+    }
+
+    // only the selected planes, like XO-CHIP
+    fn cls(&mut self) {
+        for plane in 0..self.screen.planes().len() {
+            if self.plane_selected(plane) {
+                self.screen.planes_mut()[plane] = BLANK_PLANE;
+            }
+        }
+        self.mark_all_dirty();
+        self.emit(CpuEvent::DisplayCleared);
+    }
+
+    fn scroll_down(&mut self, n: u8) {
+        let height = self.display_size().1;
+        let n = (n as usize).min(height);
+
+        for plane in 0..self.screen.planes().len() {
+            if !self.plane_selected(plane) {
+                continue;
+            }
+            let rows = &mut self.screen.planes_mut()[plane][..height];
+
+            rows.copy_within(0..height - n, n);
+            rows[..n].fill([false; HIRES_WIDTH]);
+        }
+
+        self.mark_all_dirty();
+        self.emit(CpuEvent::DisplayScrolled);
+    }
+
+    fn scroll_right(&mut self) {
+        let (width, height) = self.display_size();
+
+        for plane in 0..self.screen.planes().len() {
+            if !self.plane_selected(plane) {
+                continue;
+            }
+
+            for row in &mut self.screen.planes_mut()[plane][..height] {
+                row.copy_within(0..width - 4, 4);
+                row[..4].fill(false);
+            }
+        }
+
+        self.mark_all_dirty();
+        self.emit(CpuEvent::DisplayScrolled);
+    }
+
+    fn scroll_left(&mut self) {
+        let (width, height) = self.display_size();
+
+        for plane in 0..self.screen.planes().len() {
+            if !self.plane_selected(plane) {
+                continue;
+            }
+
+            for row in &mut self.screen.planes_mut()[plane][..height] {
+                row.copy_within(4..width, 0);
+                row[width - 4..width].fill(false);
+            }
+        }
+
+        self.mark_all_dirty();
+        self.emit(CpuEvent::DisplayScrolled);
+    }
+
+    // switching resolution wipes every plane, not just the selected ones
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen.planes_mut().fill(BLANK_PLANE);
+        self.mark_all_dirty();
+        self.emit(CpuEvent::DisplayCleared);
+    }
+
+    // XO-CHIP Fx01: x is a plane bitmask, 0 disables drawing altogether
+    fn select_planes(&mut self, x: u8, pc: usize) -> Result<(), CpuError> {
+        if !self.quirks.bit_planes {
+            return self.unknown_opcode(0xF001 | (x as u16) << 8, pc);
+        }
+
+        self.plane_mask = x & 0x3;
+        Ok(())
+    }
+
+    fn reg(&self, x: u8) -> u8 {
+        debug_assert!(x < 16, "register index {} out of range", x);
+        self.registers[x as usize]
+    }
+
+    fn set_reg(&mut self, x: u8, value: u8) {
+        debug_assert!(x < 16, "register index {} out of range", x);
+
+        if self.watched_registers.contains(&x) {
+            let location = Watch::Register(Register::from_nibble(x));
+            self.record_watch(location, self.registers[x as usize], value);
+        }
+        self.registers[x as usize] = value;
+    }
+
+    // the map is only searched when a device is attached
+    fn read_mem(&self, addr: usize) -> u8 {
+        if !self.io_hooks.is_empty() {
+            if let Some(device) = self.io_hooks.get(&addr) {
+                return device.read(addr);
+            }
+        }
+        self.memory[addr]
+    }
+
+    fn write_mem(&mut self, addr: usize, value: u8) {
+        if self.watched_memory.contains(&addr) {
+            self.record_watch(Watch::Memory(addr), self.memory[addr], value);
+        }
+        if self
+            .loaded
+            .iter()
+            .any(|&(start, end)| (start..end).contains(&addr))
+        {
+            self.self_modified = true;
+            if let Some(hook) = self.on_self_modify.as_mut() {
+                hook(addr, value);
+            }
+        }
+        if !self.io_hooks.is_empty() {
+            if let Some(device) = self.io_hooks.get_mut(&addr) {
+                device.write(addr, value);
+                return;
+            }
+        }
+        self.memory[addr] = value;
+    }
+
+    // invariant: flag-setting opcodes read their operands, write the result and
+    // only then write VF, so when x == 0xF (e.g. 8FY4) the flag always wins
+    fn vf(&mut self, flag: bool) {
+        self.set_reg(0xF, flag as u8);
+    }
+
+    fn add_xy(&mut self, x: u8, y: u8) {
+        let arg1 = self.reg(x);
+        let arg2 = self.reg(y);
+
+        let (val, overflow) = arg1.overflowing_add(arg2);
+        self.set_reg(x, val);
+
+        self.vf(overflow);
+    }
+
+    fn sub_xy(&mut self, x: u8, y: u8) {
+        let arg1 = self.reg(x);
+        let arg2 = self.reg(y);
+
+        let (val, borrow) = arg1.overflowing_sub(arg2);
+        self.set_reg(x, val);
+
+        self.vf(!borrow);
+    }
+
+    fn subn_xy(&mut self, x: u8, y: u8) {
+        let arg1 = self.reg(x);
+        let arg2 = self.reg(y);
+
+        let (val, borrow) = arg2.overflowing_sub(arg1);
+        self.set_reg(x, val);
+
+        self.vf(!borrow);
+    }
+
+    fn mul_xy(&mut self, x: u8, y: u8) {
+        let arg1 = self.reg(x);
+        let arg2 = self.reg(y);
+
+        let (val, overflow) = arg1.overflowing_mul(arg2);
+        self.set_reg(x, val);
+
+        self.vf(overflow);
+    }
+
+    // nonstandard: Vx = Vx / Vy and Vy = Vx % Vy, there is no flag so VF only
+    // changes when it is x or y. when x == y the quotient is written last and wins
+    fn div_xy(&mut self, x: u8, y: u8) -> Result<(), CpuError> {
+        let arg1 = self.reg(x);
+        let arg2 = self.reg(y);
+
+        if arg2 == 0 {
+            return Err(CpuError::DivideByZero);
+        }
+
+        self.set_reg(y, arg1 % arg2);
+        self.set_reg(x, arg1 / arg2);
+        Ok(())
+    }
+
+    fn set_logic_result(&mut self, x: u8, value: u8) {
+        self.set_reg(x, value);
+        if self.quirks.logic_quirk_reset_vf {
+            self.vf(false);
+        }
+    }
+
+    fn and_xy(&mut self, x: u8, y: u8) {
+        let arg1 = self.reg(x);
+        let arg2 = self.reg(y);
+
+        self.set_logic_result(x, arg1 & arg2);
+    }
+
+    fn or_xy(&mut self, x: u8, y: u8) {
+        let arg1 = self.reg(x);
+        let arg2 = self.reg(y);
+
+        self.set_logic_result(x, arg1 | arg2);
+    }
+
+    fn xor_xy(&mut self, x: u8, y: u8) {
+        let arg1 = self.reg(x);
+        let arg2 = self.reg(y);
+
+        self.set_logic_result(x, arg1 ^ arg2);
+    }
+
+    fn shr_xy(&mut self, x: u8, y: u8) {
+        let src = if self.quirks.shift_quirk { x } else { y };
+        let arg = self.reg(src);
+
+        self.set_reg(x, arg >> 1);
+        self.vf(arg & 0x1 == 1);
+    }
+
+    fn shl_xy(&mut self, x: u8, y: u8) {
+        let src = if self.quirks.shift_quirk { x } else { y };
+        let arg = self.reg(src);
+
+        self.set_reg(x, arg << 1);
+        self.vf(arg & 0x80 != 0);
+    }
+
+    // a target has to leave room for a whole opcode, otherwise the next fetch
+    // would read past the end of memory. the last byte can't start an opcode, so
+    // wrapping is over the len - 1 addresses that can
+    fn jump_target(&self, target: usize) -> Result<usize, CpuError> {
+        let len = self.memory.len();
+
+        if target + 1 < len {
+            Ok(target)
+        } else if self.wrap_jumps {
+            Ok(target % (len - 1))
+        } else {
+            Err(CpuError::PcOutOfBounds(target))
+        }
+    }
+
+    fn jmp(&mut self, addr: u16) -> Result<(), CpuError> {
+        self.position_in_memory = self.jump_target(addr as usize)?;
+        Ok(())
+    }
+
+    fn jmp_v0(&mut self, x: u8, addr: u16) -> Result<(), CpuError> {
+        let offset_reg = if self.quirks.jump_quirk { x } else { 0 };
+        let target = addr as usize + self.reg(offset_reg) as usize;
+
+        self.position_in_memory = self.jump_target(target)?;
+        Ok(())
+    }
+
+    fn call(&mut self, addr: u16) -> Result<(), CpuError> {
+        let target = self.jump_target(addr as usize)?;
+        let sp = self.stack_pointer;
+        let stack = &mut self.stack;
+
+        if sp >= stack.len() {
+            return Err(CpuError::StackOverflow);
+        }
+
+        stack[sp] = self.position_in_memory as u16;
+        self.stack_pointer += 1;
+        self.position_in_memory = target;
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<(), CpuError> {
+        if self.stack_pointer == 0 {
+            return Err(CpuError::StackUnderflow);
+        }
+
+        self.stack_pointer -= 1;
+        let addr = self.stack[self.stack_pointer];
+        self.position_in_memory = addr as usize;
+        Ok(())
+    }
+
+    fn ld(&mut self, x: u8, kk: u8) {
+        self.set_reg(x, kk);
+    }
+
+    // XO-CHIP: the 16-bit address is the word right after the F000
+    fn ld_i_long(&mut self, pc: usize) -> Result<(), CpuError> {
+        if !self.quirks.long_index {
+            return self.unknown_opcode(0xF000, pc);
+        }
+
+        self.index = self.read_opcode()?;
+        self.position_in_memory += 2;
+        Ok(())
+    }
+
+    fn ld_i(&mut self, addr: u16) {
+        self.index = self.wrap_index(addr);
+    }
+
+    fn wrap_index(&self, v: u16) -> u16 {
+        if self.quirks.index_12bit {
+            v & 0x0FFF
+        } else {
+            v
+        }
+    }
+
+    // memory address of I + offset, wrapped the same way I itself is and then
+    // folded into memory so a stray I can't index past the end
+    fn index_addr(&self, offset: usize) -> usize {
+        let addr = self.wrap_index(self.index.wrapping_add(offset as u16));
+        addr as usize % self.memory.len()
+    }
+
+    fn rnd(&mut self, x: u8, kk: u8) {
+        let byte = self.rng.next_byte();
+        self.set_reg(x, byte & kk);
+    }
+
+    fn draw(&mut self, x: u8, y: u8, n: u8) {
+        let (pixels, clipped_rows) = self.sprite_pixels(x, y, n);
+        let colliding_rows = self.colliding_rows(&pixels);
+        let flag = self.collision_flag(colliding_rows, clipped_rows);
+        self.xor_sprite(pixels);
+
+        // every draw that leaves VF nonzero counts, including RowCount draws that
+        // only clipped rows
+        if flag != 0 {
+            let (width, height) = self.display_size();
+            self.collisions += 1;
+            self.last_collision =
+                Some((self.reg(x) as usize % width, self.reg(y) as usize % height));
+        }
+
+        self.set_reg(0xF, flag);
+    }
+
+    // what Dxyn leaves in VF
+    fn collision_flag(&self, colliding_rows: u16, clipped_rows: usize) -> u8 {
+        if self.quirks.vf_collision_mode == CollisionMode::RowCount && self.hires {
+            (colliding_rows.count_ones() as usize + clipped_rows) as u8
+        } else {
+            (colliding_rows != 0) as u8
+        }
+    }
+
+    // draws an 8-pixel wide sprite straight from `sprite` at screen coordinates
+    // x, y, without staging it in memory. wrapping, clipping and plane selection
+    // work like Dxyn, with one plane's bytes after the other. I and VF are untouched
+    pub fn draw_bytes(&mut self, x: u8, y: u8, sprite: &[u8]) {
+        let (width, height) = self.display_size();
+        let planes = (0..self.screen.planes().len())
+            .filter(|&plane| self.plane_selected(plane))
+            .count();
+        let rows = sprite.len() / planes.max(1);
+
+        let (pixels, _) =
+            self.layout_sprite(x as usize % width, y as usize % height, rows, 8, |offset| {
+                sprite[offset]
+            });
+        self.xor_sprite(pixels);
+    }
+
+    // the sprite rows that would turn a pixel off, one bit per row. Dxyn never has
+    // more than 16 rows
+    fn colliding_rows(&self, pixels: &[SpritePixel]) -> u16 {
+        pixels
+            .iter()
+            .filter(|pixel| self.screen.planes()[pixel.plane][pixel.y][pixel.x])
+            .fold(0, |rows, pixel| {
+                rows | 1u16.checked_shl(pixel.row as u32).unwrap_or(0)
+            })
+    }
+
+    fn xor_sprite(&mut self, pixels: Vec<SpritePixel>) {
+        for SpritePixel { plane, x, y, .. } in pixels {
+            let pixel = &mut self.screen.planes_mut()[plane][y][x];
+            *pixel ^= true;
+            self.mark_dirty(x, y, 1, 1);
+
+            let on = self.pixel(x, y);
+            self.emit(CpuEvent::PixelChanged { x, y, on });
+        }
+    }
+
+    // x and y hold the coordinates like in Dxyn. nothing is drawn and VF is left
+    // alone, true whenever the draw would leave VF nonzero
+    pub fn would_collide(&self, x: Register, y: Register, n: u8) -> bool {
+        let (pixels, clipped_rows) = self.sprite_pixels(x as u8, y as u8, n);
+
+        self.collision_flag(self.colliding_rows(&pixels), clipped_rows) != 0
+    }
+
+    fn sprite_pixels(&self, x: u8, y: u8, n: u8) -> (Vec<SpritePixel>, usize) {
+        let (width, height) = self.display_size();
+        let origin_x = self.reg(x) as usize % width;
+        let origin_y = self.reg(y) as usize % height;
+
+        // SUPER-CHIP: DXY0 in hi-res draws a 16x16 sprite, two bytes per row
+        let (rows, cols) = if n == 0 && self.hires {
+            (16, 16)
+        } else {
+            (n as usize, 8)
+        };
+
+        self.layout_sprite(origin_x, origin_y, rows, cols, |offset| {
+            self.read_mem(self.index_addr(offset))
+        })
+    }
+
+    // every pixel a sprite would flip after wrapping and clipping, and how many
+    // sprite rows were clipped off the bottom. `byte` fetches sprite bytes by offset
+    fn layout_sprite(
+        &self,
+        origin_x: usize,
+        origin_y: usize,
+        rows: usize,
+        cols: usize,
+        byte: impl Fn(usize) -> u8,
+    ) -> (Vec<SpritePixel>, usize) {
+        let (width, height) = self.display_size();
+        let bytes_per_row = cols / 8;
+        let mut pixels = Vec::new();
+        let mut clipped_rows = 0;
+
+        // XO-CHIP: each selected plane takes the next sprite's worth of bytes
+        let mut sprite_start = 0;
+
+        for plane in 0..self.screen.planes().len() {
+            if !self.plane_selected(plane) {
+                continue;
+            }
+
+            for row in 0..rows {
+                let py = origin_y + row;
+                if py >= height && self.quirks.clip_sprite_y {
+                    clipped_rows = rows - row;
+                    break;
+                }
+                let py = py % height;
+
+                for col in 0..cols {
+                    let sprite_byte = byte(sprite_start + row * bytes_per_row + col / 8);
+
+                    if sprite_byte & (0x80 >> (col % 8)) == 0 {
+                        continue;
+                    }
+
+                    let px = origin_x + col;
+                    if px >= width && !self.quirks.wrap_sprites {
+                        break;
+                    }
+                    pixels.push(SpritePixel {
+                        plane,
+                        row,
+                        x: px % width,
+                        y: py,
+                    });
+                }
+            }
+
+            sprite_start += rows * bytes_per_row;
+        }
+
+        (pixels, clipped_rows)
+    }
+
+    fn ld_xy(&mut self, x: u8, y: u8) {
+        self.set_reg(x, self.reg(y));
+    }
+
+    // XO-CHIP: skipping over an F000 NNNN steps past both words
+    fn skip(&mut self) {
+        let next = self.position_in_memory;
+
+        if self.quirks.long_index
+            && next + 1 < self.memory.len()
+            && bytes_to_opcode(self.memory[next], self.memory[next + 1]) == 0xF000
+        {
+            self.position_in_memory += 4;
+        } else {
+            self.position_in_memory += 2;
+        }
+    }
+
+    fn se(&mut self, x: u8, kk: u8) {
+        if self.reg(x) == kk {
+            self.skip();
+        }
+    }
+
+    fn sne(&mut self, x: u8, kk: u8) {
+        if self.reg(x) != kk {
+            self.skip();
+        }
+    }
+
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        if let Some(state) = self.keys.get_mut(key as usize) {
+            *state = pressed;
+        }
+    }
+
+    pub fn set_key_char(&mut self, c: char, pressed: bool) {
+        if let Some(key) = map_key(c) {
+            self.set_key(key, pressed);
+        }
+    }
+
+    fn skip_if_key(&mut self, x: u8) {
+        let key = (self.reg(x) & 0xF) as usize;
+
+        if self.keys[key] {
+            self.skip();
+        }
+    }
+
+    fn skip_if_not_key(&mut self, x: u8) {
+        let key = (self.reg(x) & 0xF) as usize;
+
+        if !self.keys[key] {
+            self.skip();
+        }
+    }
+
+    fn ld_vx_dt(&mut self, x: u8) {
+        self.set_reg(x, self.delay_timer);
+    }
+
+    fn ld_dt_vx(&mut self, x: u8) {
+        self.delay_timer = self.reg(x);
+    }
+
+    fn ld_st_vx(&mut self, x: u8) {
+        self.sound_timer = self.reg(x);
+        self.update_audio();
+    }
+
+    fn add_i_vx(&mut self, x: u8) {
+        let val = self.index.wrapping_add(self.reg(x) as u16);
+        self.index = self.wrap_index(val);
+
+        if self.quirks.index_overflow_flag {
+            self.vf(val > 0x0FFF);
+        }
+    }
+
+    fn ld_f(&mut self, x: u8) {
+        let digit = (self.reg(x) & 0xF) as u16;
+        self.index = self.wrap_index(self.font_base as u16 + digit * 5);
+    }
+
+    // only digits 0-9 exist in the large font, anything above 9 gets the 9 so I
+    // never points past the end of it
+    fn ld_hf(&mut self, x: u8) {
+        let digit = (self.reg(x) & 0xF).min(9) as u16;
+        self.index = self.wrap_index(self.large_font_base() as u16 + digit * 10);
+    }
+
+    fn bcd(&mut self, x: u8) {
+        let val = self.reg(x);
+        let digits = [val / 100, (val / 10) % 10, val % 10];
+
+        for (offset, digit) in digits.into_iter().enumerate() {
+            let addr = self.index_addr(offset);
+            self.write_mem(addr, digit);
+        }
+    }
+
+    fn store_regs(&mut self, x: u8) {
+        let count = x as usize + 1;
+
+        for reg in 0..count {
+            let addr = self.index_addr(reg);
+            self.write_mem(addr, self.reg(reg as u8));
+        }
+
+        if self.quirks.load_store_quirk {
+            self.index = self.wrap_index(self.index.wrapping_add(count as u16));
+        }
+    }
+
+    fn load_regs(&mut self, x: u8) {
+        let count = x as usize + 1;
+
+        for reg in 0..count {
+            self.set_reg(reg as u8, self.read_mem(self.index_addr(reg)));
+        }
+
+        if self.quirks.load_store_quirk {
+            self.index = self.wrap_index(self.index.wrapping_add(count as u16));
+        }
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.update_audio();
+    }
+
+    fn update_audio(&mut self) {
+        let playing = self.sound_timer > 0;
+
+        if playing != self.sound_playing {
+            self.sound_playing = playing;
+            self.audio.set_playing(playing);
+            self.emit(if playing {
+                CpuEvent::SoundStart
+            } else {
+                CpuEvent::SoundStop
+            });
+        }
+    }
+
+    fn se_xy(&mut self, x: u8, y: u8) {
+        if self.reg(x) == self.reg(y) {
+            self.skip();
+        }
+    }
+
+    fn sne_xy(&mut self, x: u8, y: u8) {
+        if self.reg(x) != self.reg(y) {
+            self.skip();
+        }
+    }
+
+    fn read_key(&mut self, x: u8) {
+        #[cfg(feature = "std")]
+        if self.blocking_key_wait && self.input.is_none() {
+            self.wait_for_key(x);
+            return;
+        }
+
+        // a key counts once it has been pressed and released again. until then the
+        // instruction stalls and re-executes every step
+        match self.key_latch {
+            Some(key) if !self.keys[key as usize] => {
+                self.key_latch = None;
+                self.set_reg(x, key);
+                return;
+            }
+            Some(_) => {}
+            None => {
+                self.key_latch = self
+                    .keys
+                    .iter()
+                    .position(|&pressed| pressed)
+                    .map(|key| key as u8);
+            }
+        }
+
+        self.position_in_memory -= 2;
+    }
+
+    // terminals don't report releases, so this one stores the key on press
+    #[cfg(feature = "std")]
+    fn wait_for_key(&mut self, x: u8) {
+        log::info!("press a key...");
+        loop {
+            let event = match event::read() {
+                Ok(event) => event,
+                Err(err) => {
+                    log::error!("could not read the keyboard: {}", err);
+                    break;
+                }
+            };
+
+            if let Event::Key(event) = event {
+                match event.code {
+                    KeyCode::Char(c) => {
+                        if let Some(key) = map_key(c) {
+                            log::info!("key pressed: {}", c);
+                            self.set_reg(x, key);
+                            break;
+                        }
+                    }
+                    KeyCode::Esc => {
+                        log::info!("terminating keyboard reading");
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblerError {
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    // the label sits past 0xFFF, where a 12-bit nnn operand can't reach it
+    LabelOutOfRange(String, usize),
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssemblerError::UndefinedLabel(name) => write!(f, "label {} is never defined", name),
+            AssemblerError::DuplicateLabel(name) => write!(f, "label {} is defined twice", name),
+            AssemblerError::LabelOutOfRange(name, addr) => {
+                write!(f, "label {} at {:#06x} is out of range for nnn", name, addr)
+            }
+        }
+    }
+}
+
+impl core::error::Error for AssemblerError {}
+
+#[derive(Debug)]
+pub struct Assembler {
+    bytes: Vec<u8>,
+    // address of the first byte, labels resolve relative to it
+    origin: u16,
+    labels: BTreeMap<String, usize>,
+    // (byte offset, label) of every nnn operand still waiting for its label
+    fixups: Vec<(usize, String)>,
+    duplicate_label: Option<String>,
+}
+
+impl Default for Assembler {
+    fn default() -> Assembler {
+        Assembler::at(PROGRAM_START as u16)
+    }
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler::default()
+    }
+
+    // for code that will be loaded somewhere other than PROGRAM_START
+    pub fn at(origin: u16) -> Assembler {
+        Assembler {
+            bytes: Vec::new(),
+            origin,
+            labels: BTreeMap::new(),
+            fixups: Vec::new(),
+            duplicate_label: None,
+        }
+    }
+
+    // labels are resolved here, so they can be used before they're defined
+    pub fn assemble(mut self) -> Result<Vec<u8>, AssemblerError> {
+        if let Some(name) = self.duplicate_label {
+            return Err(AssemblerError::DuplicateLabel(name));
+        }
+
+        for (offset, name) in &self.fixups {
+            let addr = *self
+                .labels
+                .get(name)
+                .ok_or_else(|| AssemblerError::UndefinedLabel(name.clone()))?;
+
+            if addr > 0x0FFF {
+                return Err(AssemblerError::LabelOutOfRange(name.clone(), addr));
+            }
+
+            self.bytes[*offset] |= (addr >> 8) as u8 & 0x0F;
+            self.bytes[*offset + 1] = addr as u8;
+        }
+
+        Ok(self.bytes)
+    }
+
+    pub fn label(mut self, name: &str) -> Assembler {
+        let addr = self.origin as usize + self.bytes.len();
+
+        if self.labels.insert(name.into(), addr).is_some() && self.duplicate_label.is_none() {
+            self.duplicate_label = Some(name.into());
+        }
+        self
+    }
+
+    // raw data, e.g. sprite tables. nothing pads it, so an odd length leaves the
+    // following opcodes misaligned
+    pub fn db(mut self, data: &[u8]) -> Assembler {
+        self.bytes.extend_from_slice(data);
+        self
+    }
+
+    fn op(mut self, opcode: u16) -> Assembler {
+        self.bytes.extend_from_slice(&opcode_to_bytes(opcode));
+        self
+    }
+
+    fn op_xkk(self, c: u16, x: u8, kk: u8) -> Assembler {
+        self.op(c << 12 | (x as u16 & 0xF) << 8 | kk as u16)
+    }
+
+    fn op_xyd(self, c: u16, x: u8, y: u8, d: u8) -> Assembler {
+        self.op(c << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | (d as u16 & 0xF))
+    }
+
+    fn op_nnn(self, c: u16, addr: u16) -> Assembler {
+        self.op(c << 12 | (addr & 0x0FFF))
+    }
+
+    fn op_label(mut self, c: u16, name: &str) -> Assembler {
+        self.fixups.push((self.bytes.len(), name.into()));
+        self.op_nnn(c, 0)
+    }
+
+    pub fn halt(self) -> Assembler {
+        self.op(0x0000)
+    }
+
+    pub fn nop(self) -> Assembler {
+        self.op(0x0001)
+    }
+
+    pub fn cls(self) -> Assembler {
+        self.op(0x00E0)
+    }
+
+    pub fn ret(self) -> Assembler {
+        self.op(0x00EE)
+    }
+
+    pub fn scroll_down(self, n: u8) -> Assembler {
+        self.op(0x00C0 | (n as u16 & 0xF))
+    }
+
+    pub fn scroll_right(self) -> Assembler {
+        self.op(0x00FB)
+    }
+
+    pub fn scroll_left(self) -> Assembler {
+        self.op(0x00FC)
+    }
+
+    pub fn lores(self) -> Assembler {
+        self.op(0x00FE)
+    }
+
+    pub fn hires(self) -> Assembler {
+        self.op(0x00FF)
+    }
+
+    pub fn sys(self, addr: u16) -> Assembler {
+        self.op_nnn(0x0, addr)
+    }
+
+    pub fn jmp(self, addr: u16) -> Assembler {
+        self.op_nnn(0x1, addr)
+    }
+
+    pub fn call(self, addr: u16) -> Assembler {
+        self.op_nnn(0x2, addr)
+    }
+
+    pub fn jmp_label(self, name: &str) -> Assembler {
+        self.op_label(0x1, name)
+    }
+
+    pub fn call_label(self, name: &str) -> Assembler {
+        self.op_label(0x2, name)
+    }
+
+    pub fn se(self, x: Register, kk: u8) -> Assembler {
+        self.op_xkk(0x3, x as u8, kk)
+    }
+
+    pub fn sne(self, x: Register, kk: u8) -> Assembler {
+        self.op_xkk(0x4, x as u8, kk)
+    }
+
+    pub fn se_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x5, x as u8, y as u8, 0x0)
+    }
+
+    pub fn ld(self, x: Register, kk: u8) -> Assembler {
+        self.op_xkk(0x6, x as u8, kk)
+    }
+
+    pub fn ld_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0x0)
+    }
+
+    pub fn or_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0x1)
+    }
+
+    pub fn and_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0x2)
+    }
+
+    pub fn xor_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0x3)
+    }
+
+    pub fn add_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0x4)
+    }
+
+    pub fn sub_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0x5)
+    }
+
+    pub fn shr_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0x6)
+    }
+
+    pub fn subn_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0x7)
+    }
+
+    pub fn mul_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0xC)
+    }
+
+    pub fn div_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0xD)
+    }
+
+    pub fn shl_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x8, x as u8, y as u8, 0xE)
+    }
+
+    pub fn sne_xy(self, x: Register, y: Register) -> Assembler {
+        self.op_xyd(0x9, x as u8, y as u8, 0x0)
+    }
+
+    pub fn ld_i(self, addr: u16) -> Assembler {
+        self.op_nnn(0xA, addr)
+    }
+
+    pub fn ld_i_label(self, name: &str) -> Assembler {
+        self.op_label(0xA, name)
+    }
+
+    pub fn ld_i_long(self, addr: u16) -> Assembler {
+        self.op(0xF000).op(addr)
+    }
+
+    pub fn plane(self, mask: u8) -> Assembler {
+        self.op_xkk(0xF, mask, 0x01)
+    }
+
+    pub fn jmp_v0(self, addr: u16) -> Assembler {
+        self.op_nnn(0xB, addr)
+    }
+
+    pub fn rnd(self, x: Register, kk: u8) -> Assembler {
+        self.op_xkk(0xC, x as u8, kk)
+    }
+
+    pub fn draw(self, x: Register, y: Register, n: u8) -> Assembler {
+        self.op_xyd(0xD, x as u8, y as u8, n)
+    }
+
+    pub fn skip_if_key(self, x: Register) -> Assembler {
+        self.op_xkk(0xE, x as u8, 0x9E)
+    }
+
+    pub fn skip_if_not_key(self, x: Register) -> Assembler {
+        self.op_xkk(0xE, x as u8, 0xA1)
+    }
+
+    pub fn ld_vx_dt(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x07)
+    }
+
+    pub fn read_key(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x0A)
+    }
+
+    pub fn ld_dt_vx(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x15)
+    }
+
+    pub fn ld_st_vx(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x18)
+    }
+
+    pub fn add_i_vx(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x1E)
+    }
+
+    pub fn ld_f(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x29)
+    }
+
+    pub fn ld_hf(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x30)
+    }
+
+    pub fn bcd(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x33)
+    }
+
+    pub fn store_regs(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x55)
+    }
+
+    pub fn load_regs(self, x: Register) -> Assembler {
+        self.op_xkk(0xF, x as u8, 0x65)
+    }
+}
+
+// headless harness for tests, panics instead of returning errors
+pub mod testing {
+    use super::{HaltPolicy, CPU, PROGRAM_START};
+
+    // boots `program` at PROGRAM_START and runs it to a halt (or the cycle cap) so
+    // the final machine state can be asserted on
+    pub fn run_program(program: &[u8]) -> CPU {
+        run_program_on(CPU::new(), program)
+    }
+
+    // the same on a CPU that was already configured, e.g. with quirks or keys held
+    pub fn run_program_on(mut cpu: CPU, program: &[u8]) -> CPU {
+        const MAX_CYCLES: usize = 100_000;
+
+        cpu.blocking_key_wait = false;
+        cpu.on_zero_opcode = HaltPolicy::Halt;
+
+        if let Err(err) = cpu.load_program(program, PROGRAM_START) {
+            panic!("ERROR: could not load program: {}", err);
+        }
+        if let Err(err) = cpu.run_with_limit(MAX_CYCLES) {
+            panic!(
+                "ERROR: program failed at {:#06x}: {}",
+                cpu.position_in_memory, err
+            );
+        }
+
+        cpu
+    }
+}
+
+// opcodes are stored big-endian, high byte first
+pub fn opcode_to_bytes(opcode: u16) -> [u8; 2] {
+    opcode.to_be_bytes()
+}
+
+pub fn bytes_to_opcode(hi: u8, lo: u8) -> u16 {
+    u16::from_be_bytes([hi, lo])
+}
+
+pub fn nibbles(opcode: u16) -> (u8, u8, u8, u8) {
+    let c = ((opcode & 0xF000) >> 12) as u8;
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let d = (opcode & 0x000F) as u8;
+
+    (c, x, y, d)
+}
+
+// total over all 16-bit values, anything unrecognized becomes Unknown
+pub fn decode(opcode: u16) -> Instruction {
+    let (c, x, y, d) = nibbles(opcode);
+    let nnn = opcode & 0x0FFF;
+    let kk = (opcode & 0x00FF) as u8;
+
+    match (c, x, y, d) {
+        (0, 0, 0, 0) => Instruction::Halt,
+        (0, 0, 0, 1) => Instruction::Nop,
+        (0, 0, 0xC, _) => Instruction::ScrollDown(d),
+        (0, 0, 0xE, 0) => Instruction::Cls,
+        (0, 0, 0xE, 0xE) => Instruction::Ret,
+        (0, 0, 0xF, 0xB) => Instruction::ScrollRight,
+        (0, 0, 0xF, 0xC) => Instruction::ScrollLeft,
+        (0, 0, 0xF, 0xE) => Instruction::Lores,
+        (0, 0, 0xF, 0xF) => Instruction::Hires,
+        (0, _, _, _) => Instruction::Sys(nnn),
+        (0x1, _, _, _) => Instruction::Jmp(nnn),
+        (0x2, _, _, _) => Instruction::Call(nnn),
+        (0x3, _, _, _) => Instruction::Se { x, kk },
+        (0x4, _, _, _) => Instruction::Sne { x, kk },
+        (0x5, _, _, 0x0) => Instruction::SeXy { x, y },
+        (0x6, _, _, _) => Instruction::Ld { x, kk },
+        (0x8, _, _, 0x0) => Instruction::LdXy { x, y },
+        (0x8, _, _, 0x4) => Instruction::AddXy { x, y },
+        (0x8, _, _, 0x5) => Instruction::SubXy { x, y },
+        (0x8, _, _, 0x7) => Instruction::SubnXy { x, y },
+        (0x8, _, _, 0x2) => Instruction::AndXy { x, y },
+        (0x8, _, _, 0x1) => Instruction::OrXy { x, y },
+        (0x8, _, _, 0x3) => Instruction::XorXy { x, y },
+        (0x8, _, _, 0x6) => Instruction::ShrXy { x, y },
+        (0x8, _, _, 0xE) => Instruction::ShlXy { x, y },
+        (0x8, _, _, 0xC) => Instruction::MulXy { x, y },
+        (0x8, _, _, 0xD) => Instruction::DivXy { x, y },
+        (0x9, _, _, 0x0) => Instruction::SneXy { x, y },
+        (0xA, _, _, _) => Instruction::LdI(nnn),
+        (0xB, _, _, _) => Instruction::JmpV0 { x, addr: nnn },
+        (0xC, _, _, _) => Instruction::Rnd { x, kk },
+        (0xD, _, _, _) => Instruction::Draw { x, y, n: d },
+        (0xE, _, 0x9, 0xE) => Instruction::SkipIfKey(x),
+        (0xE, _, 0xA, 0x1) => Instruction::SkipIfNotKey(x),
+        (0xF, 0, 0, 0) => Instruction::LdILong,
+        (0xF, _, 0x0, 0x1) => Instruction::Plane(x),
+        (0xF, _, 0x0, 0x7) => Instruction::LdVxDt(x),
+        (0xF, _, 0x0, 0xA) => Instruction::ReadKey(x),
+        (0xF, _, 0x1, 0x5) => Instruction::LdDtVx(x),
+        (0xF, _, 0x1, 0x8) => Instruction::LdStVx(x),
+        (0xF, _, 0x1, 0xE) => Instruction::AddIVx(x),
+        (0xF, _, 0x2, 0x9) => Instruction::LdF(x),
+        (0xF, _, 0x3, 0x0) => Instruction::LdHf(x),
+        (0xF, _, 0x3, 0x3) => Instruction::Bcd(x),
+        (0xF, _, 0x5, 0x5) => Instruction::StoreRegs(x),
+        (0xF, _, 0x6, 0x5) => Instruction::LoadRegs(x),
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub mask: u16,
+    pub pattern: u16,
+    // {x} {y} {n} {kk} {nnn} are filled in from the opcode
+    pub mnemonic: &'static str,
+    pub description: &'static str,
+}
+
+impl OpcodeInfo {
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.pattern
+    }
+}
+
+// mirrors decode, more specific entries come first since lookup takes the first match
+pub const OPCODES: &[OpcodeInfo] = &[
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0x0000,
+        mnemonic: "HALT",
+        description: "stop execution, see HaltPolicy",
+    },
+    // 0x0000 depends on the HaltPolicy, so padding gets its own word. 0x0001 is in
+    // the ignored SYS range and no CHIP-8 variant gives it a meaning
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0x0001,
+        mnemonic: "NOP",
+        description: "do nothing, the canonical padding word",
+    },
+    OpcodeInfo {
+        mask: 0xFFF0,
+        pattern: 0x00C0,
+        mnemonic: "SCD {n}",
+        description: "scroll the display down n rows",
+    },
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0x00E0,
+        mnemonic: "CLS",
+        description: "clear the display",
+    },
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0x00EE,
+        mnemonic: "RET",
+        description: "return from a subroutine",
+    },
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0x00FB,
+        mnemonic: "SCR",
+        description: "scroll the display right 4 pixels",
+    },
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0x00FC,
+        mnemonic: "SCL",
+        description: "scroll the display left 4 pixels",
+    },
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0x00FE,
+        mnemonic: "LOW",
+        description: "switch to 64x32 lo-res",
+    },
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0x00FF,
+        mnemonic: "HIGH",
+        description: "switch to 128x64 hi-res",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0x0000,
+        mnemonic: "SYS {nnn}",
+        description: "machine code routine, ignored",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0x1000,
+        mnemonic: "JP {nnn}",
+        description: "jump to nnn",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0x2000,
+        mnemonic: "CALL {nnn}",
+        description: "call the subroutine at nnn",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0x3000,
+        mnemonic: "SE V{x}, {kk}",
+        description: "skip if Vx == kk",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0x4000,
+        mnemonic: "SNE V{x}, {kk}",
+        description: "skip if Vx != kk",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x5000,
+        mnemonic: "SE V{x}, V{y}",
+        description: "skip if Vx == Vy",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0x6000,
+        mnemonic: "LD V{x}, {kk}",
+        description: "Vx = kk",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x8000,
+        mnemonic: "LD V{x}, V{y}",
+        description: "Vx = Vy",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x8004,
+        mnemonic: "ADD V{x}, V{y}",
+        description: "Vx += Vy, VF = carry",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x8005,
+        mnemonic: "SUB V{x}, V{y}",
+        description: "Vx -= Vy, VF = not borrow",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x8007,
+        mnemonic: "SUBN V{x}, V{y}",
+        description: "Vx = Vy - Vx, VF = not borrow",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x8002,
+        mnemonic: "AND V{x}, V{y}",
+        description: "Vx &= Vy",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x8001,
+        mnemonic: "OR V{x}, V{y}",
+        description: "Vx |= Vy",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x8003,
+        mnemonic: "XOR V{x}, V{y}",
+        description: "Vx ^= Vy",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x8006,
+        mnemonic: "SHR V{x}, V{y}",
+        description: "shift right, VF = the bit shifted out",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x800E,
+        mnemonic: "SHL V{x}, V{y}",
+        description: "shift left, VF = the bit shifted out",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x800C,
+        mnemonic: "MUL V{x}, V{y}",
+        description: "Vx *= Vy, VF = overflow (nonstandard, unknown in standard mode)",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x800D,
+        mnemonic: "DIV V{x}, V{y}",
+        description: "Vx /= Vy, Vy = remainder (nonstandard, unknown in standard mode)",
+    },
+    OpcodeInfo {
+        mask: 0xF00F,
+        pattern: 0x9000,
+        mnemonic: "SNE V{x}, V{y}",
+        description: "skip if Vx != Vy",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0xA000,
+        mnemonic: "LD I, {nnn}",
+        description: "I = nnn",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0xB000,
+        mnemonic: "JP V0, {nnn}",
+        description: "jump to nnn + V0",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0xC000,
+        mnemonic: "RND V{x}, {kk}",
+        description: "Vx = random byte & kk",
+    },
+    OpcodeInfo {
+        mask: 0xF000,
+        pattern: 0xD000,
+        mnemonic: "DRW V{x}, V{y}, {n}",
+        description: "draw an n-row sprite at (Vx, Vy), VF = collision",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xE09E,
+        mnemonic: "SKP V{x}",
+        description: "skip if key Vx is pressed",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xE0A1,
+        mnemonic: "SKNP V{x}",
+        description: "skip if key Vx is not pressed",
+    },
+    OpcodeInfo {
+        mask: 0xFFFF,
+        pattern: 0xF000,
+        mnemonic: "LD I, LONG",
+        description: "I = the following word (XO-CHIP)",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF001,
+        mnemonic: "PLANE {x}",
+        description: "select the bit-planes drawn to (XO-CHIP)",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF007,
+        mnemonic: "LD V{x}, DT",
+        description: "Vx = delay timer",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF00A,
+        mnemonic: "LD V{x}, K",
+        description: "wait for a key and store it in Vx",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF015,
+        mnemonic: "LD DT, V{x}",
+        description: "delay timer = Vx",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF018,
+        mnemonic: "LD ST, V{x}",
+        description: "sound timer = Vx",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF01E,
+        mnemonic: "ADD I, V{x}",
+        description: "I += Vx",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF029,
+        mnemonic: "LD F, V{x}",
+        description: "I = small font digit Vx",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF030,
+        mnemonic: "LD HF, V{x}",
+        description: "I = large font digit Vx, 0-9 only",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF033,
+        mnemonic: "LD B, V{x}",
+        description: "store the BCD of Vx at I",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF055,
+        mnemonic: "LD [I], V{x}",
+        description: "store V0..=Vx at I",
+    },
+    OpcodeInfo {
+        mask: 0xF0FF,
+        pattern: 0xF065,
+        mnemonic: "LD V{x}, [I]",
+        description: "load V0..=Vx from I",
+    },
+];
+
+pub fn opcode_info(opcode: u16) -> Option<&'static OpcodeInfo> {
+    OPCODES.iter().find(|info| info.matches(opcode))
+}
+
+fn mnemonic(opcode: u16) -> Option<String> {
+    let info = opcode_info(opcode)?;
+    let (_, x, y, n) = nibbles(opcode);
+
+    let text = info
+        .mnemonic
+        .replace("{x}", &format!("{:X}", x))
+        .replace("{y}", &format!("{:X}", y))
+        .replace("{nnn}", &format!("{:#05x}", opcode & 0x0FFF))
+        .replace("{kk}", &format!("{:#04x}", opcode & 0x00FF))
+        .replace("{n}", &format!("{:#x}", n));
+
+    Some(text)
+}
+
+// (offset, opcode, operand) for each instruction. F000 takes the word after it
+// as its operand, so the address isn't decoded as an instruction of its own
+fn instructions(
+    program: &[u8],
+    order: ByteOrder,
+) -> impl Iterator<Item = (usize, u16, Option<u16>)> + '_ {
+    let word_at = move |offset: usize| {
+        program.get(offset..offset + 2).map(|word| match order {
+            ByteOrder::BigEndian => bytes_to_opcode(word[0], word[1]),
+            ByteOrder::LittleEndian => bytes_to_opcode(word[1], word[0]),
+        })
+    };
+    let mut offset = 0;
+
+    core::iter::from_fn(move || {
+        let start = offset;
+        let opcode = word_at(start)?;
+        let operand = if opcode == 0xF000 {
+            word_at(start + 2)
+        } else {
+            None
+        };
+        offset += if operand.is_some() { 4 } else { 2 };
+
+        Some((start, opcode, operand))
+    })
+}
+
+pub fn disassemble(program: &[u8], start_address: usize) -> Vec<(usize, u16, String)> {
+    let mut lines: Vec<_> = instructions(program, ByteOrder::BigEndian)
+        .map(|(offset, opcode, operand)| {
+            let text = match operand {
+                Some(address) => format!("LD I, {:#06x}", address),
+                None => mnemonic(opcode).unwrap_or_else(|| format!("DW {:#06x}", opcode)),
+            };
+            (start_address + offset, opcode, text)
+        })
+        .collect();
+
+    // odd trailing byte, there's no second half to form an opcode with
+    if program.len() % 2 == 1 {
+        let byte = program[program.len() - 1];
+        lines.push((
+            start_address + program.len() - 1,
+            byte as u16,
+            format!("DB {:#04x}", byte),
+        ));
+    }
+
+    lines
+}
+
+// a linear scan over every word, not a control-flow analysis: sprite data that
+// is never executed gets reported too, and code at odd offsets is decoded out of
+// phase. returns (offset into `program`, opcode) for each Unknown. MUL and DIV
+// decode fine here even though a CPU in standard mode refuses them, use
+// CPU::validate to check against an actual configuration
+pub fn validate(program: &[u8]) -> Vec<(usize, u16)> {
+    instructions(program, ByteOrder::BigEndian)
+        .filter(|&(_, opcode, _)| matches!(decode(opcode), Instruction::Unknown(_)))
+        .map(|(offset, opcode, _)| (offset, opcode))
+        .collect()
+}
+
+// 1 2 3 C      1 2 3 4
+// 4 5 6 D  ->  q w e r
+// 7 8 9 E      a s d f
+// A 0 B F      z x c v
+pub fn map_key(c: char) -> Option<u8> {
+    let key = match c.to_ascii_lowercase() {
+        '1' => 0x1,
+        '2' => 0x2,
+        '3' => 0x3,
+        '4' => 0xC,
+        'q' => 0x4,
+        'w' => 0x5,
+        'e' => 0x6,
+        'r' => 0xD,
+        'a' => 0x7,
+        's' => 0x8,
+        'd' => 0x9,
+        'f' => 0xE,
+        'z' => 0xA,
+        'x' => 0x0,
+        'c' => 0xB,
+        'v' => 0xF,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CPU;
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "register index 16 out of range")]
+    fn reg_rejects_index_16() {
+        CPU::new().reg(16);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "register index 16 out of range")]
+    fn set_reg_rejects_index_16() {
+        CPU::new().set_reg(16, 0);
+    }
+}
@@ -1,226 +1,312 @@
-use crossterm::event::{self, Event, KeyCode};
-
-#[derive(Debug)]
-struct CPU {
-    registers: [u8; 16],
-    position_in_memory: usize,
-    memory: [u8; 0x1000],
-    stack: [u16; 16],
-    stack_pointer: usize,
-}
+#[cfg(feature = "audio")]
+use clayton_cpu::BeepAudio;
+use clayton_cpu::{
+    disassemble, map_key, Clock, HaltPolicy, InputConfig, KeyTracker, RenderConfig, StepOutcome,
+    SystemClock, CPU, PROGRAM_START,
+};
+use crossterm::cursor::{Hide, Show};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
 
-impl CPU {
-    fn new() -> CPU {
-        CPU {
-            registers: [0; 16],
-            memory: [0; 4096],
-            position_in_memory: 0,
-            stack: [0; 16],
-            stack_pointer: 0,
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}: {}", record.level(), record.args());
         }
     }
 
-    fn load_program(&mut self, program: &[u8], start_address: usize) {
-        self.memory[start_address..(start_address + program.len())].copy_from_slice(program);
-    }
+    fn flush(&self) {}
+}
 
-    fn read_opcode(&self) -> u16 {
-        let p = self.position_in_memory;
-        let op_byte1 = self.memory[p] as u16;
-        let op_byte2 = self.memory[p + 1] as u16;
+static LOGGER: StderrLogger = StderrLogger;
 
-        op_byte1 << 8 | op_byte2
-    }
+fn enter_terminal() -> crossterm::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, Hide)
+}
+
+fn leave_terminal() -> crossterm::Result<()> {
+    execute!(io::stdout(), Show, LeaveAlternateScreen)?;
+    disable_raw_mode()
+}
 
-    fn run(&mut self) {
-        loop {
-            let opcode = self.read_opcode();
-            self.position_in_memory += 2;
+const INSTRUCTIONS_PER_FRAME: usize = 10;
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
 
-            let c = ((opcode & 0xF000) >> 12) as u8;
-            let x = ((opcode & 0x0F00) >> 8) as u8;
-            let y = ((opcode & 0x00F0) >> 4) as u8;
-            let d = ((opcode & 0x000F) >> 0) as u8;
+// one iteration per 60Hz frame: poll input, run a batch of instructions, tick the
+// timers once and redraw. returns when Esc is pressed
+fn frame_loop(cpu: &mut CPU) -> Result<(), Box<dyn std::error::Error>> {
+    let clock = SystemClock::new();
+    let mut tracker = KeyTracker::new(InputConfig::default());
+    let render = RenderConfig::default();
 
-            let nnn = opcode & 0x0FFF;
-            let kk = (opcode & 0x00FF) as u8;
+    // everything after this first full frame only redraws what changed
+    cpu.render_terminal(&render)?;
 
-            match (c, x, y, d) {
-                (0, 0, 0, 0) => {
-                    println!("terminating execution.");
-                    return;
+    loop {
+        let frame_start = Instant::now();
+        let now = clock.elapsed();
+
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                match key.code {
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Char(c) => {
+                        if let Some(event) = map_key(c).and_then(|key| tracker.press(key, now)) {
+                            cpu.set_key(event.key, event.pressed);
+                        }
+                    }
+                    _ => {}
                 }
-                (0, 0, 0xE, 0xE) => self.ret(),
-                (0x1, _, _, _) => self.jmp(nnn),
-                (0x2, _, _, _) => self.call(nnn),
-                (0x3, _, _, _) => self.se(x, kk),
-                (0x4, _, _, _) => self.sne(x, kk),
-                (0x6, _, _, _) => self.ld(x, kk),
-                (0x8, _, _, 0x4) => self.add_xy(x, y),
-                (0x8, _, _, 0x5) => self.sub_xy(x, y),
-                (0x8, _, _, 0x2) => self.and_xy(x, y),
-                (0x8, _, _, 0x1) => self.or_xy(x, y),
-                (0x8, _, _, 0x3) => self.xor_xy(x, y),
-                (0x8, _, _, 0xC) => self.mul_xy(x, y),
-                (0x8, _, _, 0xD) => self.div_xy(x, y),
-                (0xF, 0, 0, 0xA) => self.read_key(),
-                _ => todo!("opcode {:04x}", opcode),
             }
         }
-    }
+        for event in tracker.expire(now) {
+            cpu.set_key(event.key, event.pressed);
+        }
 
-    fn add_xy(&mut self, x: u8, y: u8) {
-        let arg1 = self.registers[x as usize];
-        let arg2 = self.registers[y as usize];
+        for _ in 0..INSTRUCTIONS_PER_FRAME {
+            if cpu.is_halted() || cpu.step()? == StepOutcome::AwaitingVBlank {
+                break;
+            }
+        }
 
-        let (val, overflow) = arg1.overflowing_add(arg2);
-        self.registers[x as usize] = val;
+        cpu.tick_timers();
+        cpu.render_terminal_dirty(&render)?;
 
-        if overflow {
-            self.registers[0xF] = 1;
-        } else {
-            self.registers[0xF] = 0;
+        if let Some(rest) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+            thread::sleep(rest);
         }
     }
+}
 
-    fn sub_xy(&mut self, x: u8, y: u8) {
-        let arg1 = self.registers[x as usize];
-        let arg2 = self.registers[y as usize];
-
-        let (val, borrow) = arg1.overflowing_sub(arg2);
-        self.registers[x as usize] = val;
+fn current_instruction(cpu: &CPU) -> String {
+    let pc = cpu.pc();
+    let end = (pc + 2).min(cpu.memory().len());
 
-        if borrow {
-            self.registers[0xF] = 0;
-        } else {
-            self.registers[0xF] = 1;
-        }
+    match disassemble(&cpu.memory()[pc.min(end)..end], pc).first() {
+        Some((addr, opcode, text)) => format!("{:#06x}: {:04x}  {}", addr, opcode, text),
+        None => format!("{:#06x}: <outside of memory>", pc),
     }
+}
 
-    fn mul_xy(&mut self, x: u8, y: u8) {
-        let arg1 = self.registers[x as usize];
-        let arg2 = self.registers[y as usize];
+fn parse_number(text: &str) -> Option<usize> {
+    let digits = text.trim_start_matches("0x");
+    usize::from_str_radix(digits, 16).ok()
+}
 
-        let (val, overflow) = arg1.overflowing_mul(arg2);
-        self.registers[x as usize] = val;
+const CONTINUE_LIMIT: usize = 1_000_000;
 
-        if overflow {
-            self.registers[0xF] = 1;
-        } else {
-            self.registers[0xF] = 0;
-        }
+// one command line, None at the end of input. on a terminal the keys come in
+// through crossterm in raw mode, piped input (scripts, tests) is read line by line
+fn read_command(stdout: &mut io::Stdout) -> io::Result<Option<String>> {
+    if !io::stdin().is_terminal() {
+        let mut line = String::new();
+        let read = io::stdin().lock().read_line(&mut line)?;
+        return Ok((read > 0).then_some(line));
     }
 
-    fn div_xy(&mut self, x: u8, y: u8) {
-        let arg1 = self.registers[x as usize];
-        let arg2 = self.registers[y as usize];
+    enable_raw_mode()?;
+    let line = read_key_line(stdout);
+    disable_raw_mode()?;
+    println!();
+    line
+}
 
-        if arg2 == 0 {
-            panic!("ERROR: division by zero is not allowed");
+// Enter ends the line, Backspace edits it and Ctrl-C or Ctrl-D on an empty line
+// ends the session
+fn read_key_line(stdout: &mut io::Stdout) -> io::Result<Option<String>> {
+    let mut line = String::new();
+
+    loop {
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => return Ok(Some(line)),
+            KeyCode::Char('c') if control => return Ok(None),
+            KeyCode::Char('d') if control && line.is_empty() => return Ok(None),
+            KeyCode::Char(_) if control => {}
+            KeyCode::Char(c) => {
+                line.push(c);
+                print!("{}", c);
+            }
+            KeyCode::Backspace if line.pop().is_some() => print!("\x08 \x08"),
+            _ => {}
         }
-
-        self.registers[0xF] = arg1 % arg2;
-        self.registers[x as usize] = arg1 / arg2;
+        stdout.flush()?;
     }
+}
 
-    fn and_xy(&mut self, x: u8, y: u8) {
-        let arg1 = self.registers[x as usize];
-        let arg2 = self.registers[y as usize];
-
-        self.registers[x as usize] = arg1 & arg2;
+// one command per line, numbers are hex with an optional 0x prefix:
+//   s          step one instruction
+//   c          continue until a breakpoint, an error, a halt, a stall or
+//              CONTINUE_LIMIT instructions
+//   r          dump the registers
+//   m ADDR LEN hexdump LEN bytes starting at ADDR
+//   b ADDR     set a breakpoint at ADDR
+//   k KEY      hold KEY down until the next instruction runs
+//   q          quit
+fn debug_repl(cpu: &mut CPU) -> io::Result<()> {
+    let mut stdout = io::stdout();
+
+    println!("{}", current_instruction(cpu));
+    loop {
+        print!("> ");
+        stdout.flush()?;
+
+        let line = match read_command(&mut stdout)? {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["s"] => {
+                if let Err(err) = cpu.step() {
+                    println!("{}", err);
+                }
+                cpu.release_keys();
+                println!("{}", current_instruction(cpu));
+            }
+            ["c"] => {
+                for steps in 1.. {
+                    let pc = cpu.pc();
+
+                    match cpu.step() {
+                        Ok(_) if cpu.is_halted() => {
+                            println!("halted");
+                            break;
+                        }
+                        // a key wait or a jump to itself, nothing changes until input arrives
+                        Ok(_) if cpu.pc() == pc => {
+                            println!("stalled at {:#06x}", pc);
+                            break;
+                        }
+                        Ok(_) if steps == CONTINUE_LIMIT => {
+                            println!("stopped after {} instructions", steps);
+                            break;
+                        }
+                        Ok(_) => cpu.release_keys(),
+                        Err(err) => {
+                            println!("{}", err);
+                            break;
+                        }
+                    }
+                }
+                println!("{}", current_instruction(cpu));
+            }
+            ["r"] => println!("{}", cpu.dump_registers()),
+            ["m", addr, len] => match (parse_number(addr), parse_number(len)) {
+                (Some(addr), Some(len)) => print!("{}", cpu.dump_memory(addr, len)),
+                _ => println!("usage: m ADDR LEN"),
+            },
+            ["b", addr] => match parse_number(addr) {
+                Some(addr) => cpu.add_breakpoint(addr),
+                None => println!("usage: b ADDR"),
+            },
+            ["k", key] => match parse_number(key) {
+                Some(key) if key < 16 => cpu.set_key(key as u8, true),
+                _ => println!("usage: k KEY"),
+            },
+            ["q"] => return Ok(()),
+            [] => {}
+            _ => println!("commands: s, c, r, m ADDR LEN, b ADDR, k KEY, q"),
+        }
     }
+}
 
-    fn or_xy(&mut self, x: u8, y: u8) {
-        let arg1 = self.registers[x as usize];
-        let arg2 = self.registers[y as usize];
-
-        self.registers[x as usize] = arg1 | arg2;
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let debug = args.iter().any(|arg| arg == "--debug");
+    let rom_path = args.iter().find(|arg| !arg.starts_with("--"));
+
+    // info records would scribble over the alternate screen, so only warnings and up
+    // unless the debugger is running on the plain terminal
+    let level = if debug {
+        LevelFilter::Info
+    } else {
+        LevelFilter::Warn
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
     }
 
-    fn xor_xy(&mut self, x: u8, y: u8) {
-        let arg1 = self.registers[x as usize];
-        let arg2 = self.registers[y as usize];
-
-        self.registers[x as usize] = arg1 ^ arg2;
-    }
+    let mut cpu = CPU::new();
 
-    fn jmp(&mut self, addr: u16) {
-        self.position_in_memory = addr as usize;
+    #[cfg(feature = "audio")]
+    {
+        cpu = match BeepAudio::new() {
+            Ok(audio) => cpu.with_audio_sink(Box::new(audio)),
+            Err(err) => {
+                log::warn!("audio disabled: {}", err);
+                cpu
+            }
+        };
     }
 
-    fn call(&mut self, addr: u16) {
-        let sp = self.stack_pointer;
-        let stack = &mut self.stack;
-
-        if sp > stack.len() {
-            panic!("ERROR: stack overflow");
+    if let Some(path) = rom_path {
+        if let Err(err) = cpu.load_rom_from_file(Path::new(path), PROGRAM_START) {
+            log::error!("could not load {}: {}", path, err);
+            std::process::exit(1);
         }
-
-        stack[sp] = self.position_in_memory as u16;
-        self.stack_pointer += 1;
-        self.position_in_memory = addr as usize;
-    }
-
-    fn ret(&mut self) {
-        if self.stack_pointer == 0 {
-            panic!("ERROR: stack underflow");
+    } else {
+        // the demo ends on a 0x0000 word and shows off MUL and DIV, real ROMs keep
+        // the default no-op and standard mode
+        cpu.set_zero_opcode_policy(HaltPolicy::Halt);
+        cpu.set_standard_mode(false);
+
+        if let Err(err) = cpu.load_program(&demo_program(), PROGRAM_START) {
+            log::error!("{}", err);
+            std::process::exit(1);
         }
-
-        self.stack_pointer -= 1;
-        let addr = self.stack[self.stack_pointer];
-        self.position_in_memory = addr as usize;
     }
 
-    fn ld(&mut self, x: u8, kk: u8) {
-        self.registers[x as usize] = kk;
-    }
-
-    fn se(&mut self, x: u8, kk: u8) {
-        if self.registers[x as usize] == kk {
-            self.position_in_memory += 2;
+    if debug {
+        if let Err(err) = debug_repl(&mut cpu) {
+            log::error!("{}", err);
+            std::process::exit(1);
         }
+        return;
     }
 
-    fn sne(&mut self, x: u8, kk: u8) {
-        if self.registers[x as usize] != kk {
-            self.position_in_memory += 2;
-        }
+    if let Err(err) = enter_terminal() {
+        log::error!("could not set up the terminal: {}", err);
+        std::process::exit(1);
     }
 
-    fn read_key(&mut self) {
-        println!("press a key...");
-        loop {
-            if let Event::Key(event) = event::read().unwrap() {
-                match event.code {
-                    KeyCode::Char(c) => {
-                        println!("key pressed: {}", c);
-                        // save the key to v0 register (example)
-                        self.registers[0] = c as u8;
-                        break;
-                    }
-                    KeyCode::Esc => {
-                        println!("terminating keyboard reading");
-                        break;
-                    }
-                    _ => {}
-                }
-            }
-        }
+    let result = frame_loop(&mut cpu);
+    let _ = leave_terminal();
+
+    if let Err(err) = result {
+        log::error!("{}", err);
+        std::process::exit(1);
     }
 }
 
-fn main() {
-    let mut cpu = CPU::new();
-
-    let program: Vec<u8> = vec![
+fn demo_program() -> Vec<u8> {
+    vec![
         0x60, 0x05, // LD V0, 5
         0x61, 0x0A, // LD V1, 10
         0x80, 0x1C, // MUL V0, V1
         0x80, 0x1D, // DIV V0, V1
         0xF0, 0x0A, // LD V0, K (Leitura de tecla)
-        0x00, 0x00, // NOP (fim da execução)
-    ];
-
-    cpu.load_program(&program, 0x000);
-    cpu.run();
+        0x00, 0x00, // HALT (fim da execução, o demo usa HaltPolicy::Halt)
+    ]
 }
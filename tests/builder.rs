@@ -0,0 +1,47 @@
+use clayton_cpu::{CpuError, CPU, MEMORY_SIZE, PROGRAM_START};
+
+#[test]
+fn builder_applies_seed_and_memory_size() {
+    let cpu = CPU::builder()
+        .seed(42)
+        .memory_size(0x2000)
+        .start_address(0x600)
+        .build();
+
+    assert_eq!(cpu.seed(), Some(42));
+    assert_eq!(cpu.memory().len(), 0x2000);
+    assert_eq!(cpu.pc(), 0x600);
+}
+
+#[test]
+fn defaults_match_new() {
+    let cpu = CPU::builder().build();
+
+    assert_eq!(cpu.seed(), None);
+    assert_eq!(cpu.memory().len(), MEMORY_SIZE);
+    assert_eq!(cpu.pc(), PROGRAM_START);
+}
+
+#[test]
+fn try_build_reports_bad_configurations() {
+    let err = |builder: clayton_cpu::CpuBuilder| builder.try_build().unwrap_err();
+
+    assert_eq!(
+        err(CPU::builder().memory_size(0x800)),
+        CpuError::MemoryTooSmall(0x800)
+    );
+    assert_eq!(
+        err(CPU::builder().start_address(0x1000)),
+        CpuError::AddressOutOfBounds(0x1000)
+    );
+    assert_eq!(
+        err(CPU::builder().font_base(0xFF0)),
+        CpuError::FontOutOfRange(0xFF0)
+    );
+}
+
+#[test]
+#[should_panic(expected = "can't hold the interpreter area")]
+fn build_panics_on_too_little_memory() {
+    CPU::builder().memory_size(0x800).build();
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "std")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn debug_session(commands: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_clayton-cpu"))
+        .arg("--debug")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn continue_stops_on_the_demo_key_wait() {
+    let out = debug_session("c\nq\n");
+
+    assert!(out.contains("stalled at 0x0208"), "{}", out);
+}
+
+#[test]
+fn stepping_prints_the_next_instruction() {
+    let out = debug_session("s\ns\nr\nq\n");
+
+    assert!(out.contains("0x0200: 6005  LD V0, 0x05"), "{}", out);
+    assert!(out.contains("0x0202: 610a  LD V1, 0x0a"), "{}", out);
+    assert!(out.contains("0x0204: 801c  MUL V0, V1"), "{}", out);
+    assert!(out.contains("V0=05 V1=0a"), "{}", out);
+}
@@ -0,0 +1,313 @@
+use clayton_cpu::testing::run_program;
+use clayton_cpu::Register::*;
+use clayton_cpu::{Assembler, CpuError, HaltPolicy, Watch, CPU, PROGRAM_START};
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// the demo program main runs without a ROM
+const DEMO: [u8; 12] = [
+    0x60, 0x05, 0x61, 0x0A, 0x80, 0x1C, 0x80, 0x1D, 0xF0, 0x0A, 0x00, 0x00,
+];
+
+fn demo_cpu() -> CPU {
+    let mut cpu = CPU::boot(&DEMO).unwrap();
+    cpu.set_standard_mode(false);
+    cpu.set_blocking_key_wait(false);
+    cpu
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+#[cfg(feature = "std")]
+impl SharedBuffer {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+fn trace_line(pc: u16, opcode: u16, v0: u8, v1: u8) -> String {
+    let mut line = format!("{:#06x} {:04x} V0={:02x} V1={:02x}", pc, opcode, v0, v1);
+    for i in 2..16 {
+        line.push_str(&format!(" V{:X}=00", i));
+    }
+    line + " I=0000 SP=0"
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn trace_log_of_the_demo() {
+    let log = SharedBuffer::default();
+    let mut cpu = demo_cpu();
+    cpu.enable_trace_log(Box::new(log.clone()));
+
+    for _ in 0..4 {
+        cpu.step().unwrap();
+    }
+
+    let text = log.text();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            trace_line(0x200, 0x6005, 0x05, 0x00),
+            trace_line(0x202, 0x610a, 0x05, 0x0a),
+            trace_line(0x204, 0x801c, 0x32, 0x0a),
+            trace_line(0x206, 0x801d, 0x05, 0x00),
+        ]
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn trace_log_hex_is_lowercase() {
+    let log = SharedBuffer::default();
+    let mut cpu = CPU::boot(&Assembler::new().ld_i(0xABC).assemble().unwrap()).unwrap();
+    cpu.enable_trace_log(Box::new(log.clone()));
+
+    cpu.step().unwrap();
+
+    assert!(log.text().contains(" I=0abc SP=0\n"), "{}", log.text());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn fast_forward_detaches_the_trace_log() {
+    let log = SharedBuffer::default();
+    let mut cpu = demo_cpu();
+    cpu.enable_trace_log(Box::new(log.clone()));
+
+    cpu.fast_forward(2).unwrap();
+    assert_eq!(log.text(), "");
+    assert_eq!(cpu.register(V1), 0x0a);
+
+    cpu.step().unwrap();
+    assert_eq!(log.text().lines().count(), 1);
+    assert!(log.text().starts_with("0x0204 801c"));
+    assert_eq!(cpu.pc(), PROGRAM_START + 6);
+}
+
+#[test]
+fn with_trace_sees_every_opcode() {
+    let opcodes = Rc::new(RefCell::new(Vec::new()));
+    let seen = opcodes.clone();
+    let program = Assembler::new()
+        .ld(V0, 1)
+        .call_label("sub")
+        .jmp_label("end")
+        .label("sub")
+        .add_xy(V0, V0)
+        .ret()
+        .label("end")
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program)
+        .unwrap()
+        .with_trace(move |_pc, opcode| seen.borrow_mut().push(opcode));
+
+    cpu.run_with_limit(5).unwrap();
+
+    assert_eq!(*opcodes.borrow(), [0x6001, 0x2206, 0x8004, 0x00EE, 0x120A]);
+}
+
+#[test]
+fn breakpoints_stop_run_and_let_it_resume() {
+    let program = Assembler::new()
+        .ld(V0, 1)
+        .ld(V1, 2)
+        .ld(V2, 3)
+        .halt()
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.set_zero_opcode_policy(HaltPolicy::Halt);
+    cpu.add_breakpoint(0x204);
+
+    assert_eq!(cpu.run(), Err(CpuError::BreakpointHit(0x204)));
+    assert_eq!(cpu.pc(), 0x204);
+    assert_eq!(cpu.register(V1), 2);
+    assert_eq!(cpu.register(V2), 0);
+
+    assert_eq!(cpu.run(), Ok(()));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.register(V2), 3);
+}
+
+// counts V0 up to 5 with two 8xy4 per round
+fn counting_loop() -> Vec<u8> {
+    Assembler::new()
+        .ld(V0, 0)
+        .ld(V1, 1)
+        .label("loop")
+        .add_xy(V0, V1)
+        .add_xy(V2, V1)
+        .se(V0, 5)
+        .jmp_label("loop")
+        .halt()
+        .assemble()
+        .unwrap()
+}
+
+#[test]
+fn cycle_count_and_opcode_stats() {
+    let cpu = run_program(&counting_loop());
+
+    // 2 loads, 5 rounds of add, add and se, 4 jumps back and the halt
+    assert_eq!(cpu.cycle_count(), 2 + 5 * 3 + 4 + 1);
+    let stats: Vec<(u8, u64)> = cpu.opcode_stats().iter().map(|(&k, &v)| (k, v)).collect();
+    assert_eq!(stats, [(0x0, 1), (0x1, 4), (0x3, 5), (0x6, 2), (0x8, 10)]);
+
+    let hottest = cpu.opcode_stats().iter().max_by_key(|(_, &count)| count);
+    assert_eq!(hottest, Some((&0x8, &10)));
+}
+
+#[test]
+fn register_watchpoints_report_old_and_new() {
+    let program = Assembler::new()
+        .ld(V5, 0x11)
+        .ld(V5, 0x11)
+        .ld(V5, 0x22)
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.watch_register(V5);
+
+    let hit = Err(CpuError::Watchpoint {
+        location: Watch::Register(V5),
+        old: 0x00,
+        new: 0x11,
+    });
+    assert_eq!(cpu.step(), hit);
+    assert_eq!(cpu.register(V5), 0x11);
+    // writing the same value again doesn't count
+    assert!(cpu.step().is_ok());
+
+    let hit = Err(CpuError::Watchpoint {
+        location: Watch::Register(V5),
+        old: 0x11,
+        new: 0x22,
+    });
+    assert_eq!(cpu.step(), hit);
+    assert_eq!(cpu.pc(), PROGRAM_START + 6);
+}
+
+#[test]
+fn memory_watchpoints_report_old_and_new() {
+    let program = Assembler::new()
+        .ld(V1, 0x7F)
+        .ld_i(0x300)
+        .store_regs(V1)
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.watch_memory(0x301);
+
+    assert_eq!(
+        cpu.run_with_limit(3),
+        Err(CpuError::Watchpoint {
+            location: Watch::Memory(0x301),
+            old: 0x00,
+            new: 0x7F,
+        })
+    );
+}
+
+#[test]
+fn coverage_of_the_demo() {
+    let mut cpu = demo_cpu();
+    cpu.set_zero_opcode_policy(HaltPolicy::Halt);
+
+    // stalls on Fx0A until the key goes down and up again
+    cpu.run_with_limit(10).unwrap();
+    cpu.set_key(0x7, true);
+    cpu.step().unwrap();
+    cpu.set_key(0x7, false);
+    cpu.run().unwrap();
+    assert_eq!(cpu.register(V0), 0x7);
+
+    let mut opcodes: Vec<u16> = cpu.executed_opcodes().collect();
+    opcodes.sort_unstable();
+    assert_eq!(opcodes, [0x0000, 0x6005, 0x610A, 0x801C, 0x801D, 0xF00A]);
+
+    cpu.clear_coverage();
+    assert_eq!(cpu.executed_opcodes().count(), 0);
+}
+
+#[test]
+fn run_until_stops_on_the_predicate() {
+    let mut cpu = CPU::boot(&counting_loop()).unwrap();
+    cpu.set_zero_opcode_policy(HaltPolicy::Halt);
+
+    // two lds, two trips round the loop and the third add
+    assert_eq!(cpu.run_until(|cpu| cpu.register(V0) == 3, 1000), Ok(true));
+    assert_eq!(cpu.cycle_count(), 11);
+    assert_eq!(cpu.pc(), PROGRAM_START + 6);
+
+    // already holds, nothing runs
+    assert_eq!(cpu.run_until(|cpu| cpu.register(V0) == 3, 1000), Ok(true));
+    assert_eq!(cpu.cycle_count(), 11);
+
+    // the cap comes first
+    assert_eq!(cpu.run_until(|cpu| cpu.register(V0) == 5, 2), Ok(false));
+    assert_eq!(cpu.cycle_count(), 13);
+
+    // V0 never gets past 5, the halt comes first
+    assert_eq!(cpu.run_until(|cpu| cpu.register(V0) == 9, 1000), Ok(false));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.register(V0), 5);
+}
+
+// stores V0 and V1 at `addr`, the halt after the store is part of the ROM too
+fn store_at(addr: u16) -> Vec<u8> {
+    Assembler::new()
+        .ld(V0, 0xAA)
+        .ld(V1, 0xBB)
+        .ld_i(addr)
+        .store_regs(V1)
+        .halt()
+        .assemble()
+        .unwrap()
+}
+
+#[test]
+fn storing_over_the_rom_is_self_modification() {
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let hook_writes = writes.clone();
+    let mut cpu = CPU::boot(&store_at(0x208))
+        .unwrap()
+        .with_self_modify_hook(move |addr, value| hook_writes.borrow_mut().push((addr, value)));
+
+    cpu.run_with_limit(4).unwrap();
+
+    assert!(cpu.self_modified());
+    // both bytes of the halt word
+    assert_eq!(*writes.borrow(), [(0x208, 0xAA), (0x209, 0xBB)]);
+}
+
+#[test]
+fn storing_elsewhere_is_not_self_modification() {
+    let mut cpu = CPU::boot(&store_at(0x300))
+        .unwrap()
+        .with_self_modify_hook(|_, _| panic!("not a ROM byte"));
+
+    cpu.run_with_limit(4).unwrap();
+
+    assert!(!cpu.self_modified());
+    assert_eq!(cpu.memory()[0x300..0x302], [0xAA, 0xBB]);
+}
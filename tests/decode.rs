@@ -0,0 +1,75 @@
+use clayton_cpu::{
+    bytes_to_opcode, decode, nibbles, opcode_info, opcode_to_bytes, Instruction, OpcodeInfo,
+    OPCODES,
+};
+
+#[test]
+fn every_opcode_decodes() {
+    // unknown values come back untouched so errors can report them
+    for opcode in 0..=u16::MAX {
+        if let Instruction::Unknown(raw) = decode(opcode) {
+            assert_eq!(raw, opcode);
+        }
+    }
+}
+
+#[test]
+fn every_decoded_opcode_has_one_table_entry() {
+    let is_sys = |info: &&OpcodeInfo| info.mnemonic == "SYS {nnn}";
+
+    for opcode in 0..=u16::MAX {
+        let matching: Vec<&OpcodeInfo> =
+            OPCODES.iter().filter(|info| info.matches(opcode)).collect();
+
+        match decode(opcode) {
+            Instruction::Unknown(_) => assert!(matching.is_empty(), "{:04x}", opcode),
+            Instruction::Sys(_) => assert!(matching.len() == 1 && is_sys(&matching[0])),
+            _ => {
+                // SYS covers the whole 0 class, so the 00xx entries also match it.
+                // they come first and lookup takes them
+                let specific: Vec<&OpcodeInfo> =
+                    matching.into_iter().filter(|info| !is_sys(info)).collect();
+                assert_eq!(specific.len(), 1, "{:04x}", opcode);
+                assert_eq!(opcode_info(opcode), Some(specific[0]));
+            }
+        }
+    }
+}
+
+#[test]
+fn operands_come_from_the_right_nibbles() {
+    assert_eq!(decode(0x1ABC), Instruction::Jmp(0xABC));
+    assert_eq!(decode(0x3A42), Instruction::Se { x: 0xA, kk: 0x42 });
+    assert_eq!(decode(0x8AB4), Instruction::AddXy { x: 0xA, y: 0xB });
+    assert_eq!(decode(0xD125), Instruction::Draw { x: 1, y: 2, n: 5 });
+    assert_eq!(
+        decode(0xBA10),
+        Instruction::JmpV0 {
+            x: 0xA,
+            addr: 0xA10
+        }
+    );
+    assert_eq!(decode(0x00C7), Instruction::ScrollDown(7));
+    assert_eq!(decode(0xF000), Instruction::LdILong);
+    assert_eq!(decode(0xF100), Instruction::Unknown(0xF100));
+}
+
+#[test]
+fn opcode_bytes_round_trip() {
+    for opcode in [0x0000, 0x00E0, 0x1234, 0x8AB4, 0xD01F, 0xF000, 0xFFFF] {
+        let [hi, lo] = opcode_to_bytes(opcode);
+        assert_eq!(bytes_to_opcode(hi, lo), opcode, "{:04x}", opcode);
+    }
+
+    // high byte first
+    assert_eq!(opcode_to_bytes(0xF000), [0xF0, 0x00]);
+    assert_eq!(opcode_to_bytes(0x00E0), [0x00, 0xE0]);
+    assert_eq!(bytes_to_opcode(0x00, 0xE0), 0x00E0);
+}
+
+#[test]
+fn nibbles_split_c_x_y_d() {
+    assert_eq!(nibbles(0x8AB4), (0x8, 0xA, 0xB, 0x4));
+    assert_eq!(nibbles(0xF000), (0xF, 0, 0, 0));
+    assert_eq!(nibbles(0x00E0), (0, 0, 0xE, 0));
+}
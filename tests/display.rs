@@ -0,0 +1,508 @@
+use clayton_cpu::testing::run_program_on;
+use clayton_cpu::Register::*;
+use clayton_cpu::{Assembler, CollisionMode, Profile, Quirks, CPU, PROGRAM_START};
+
+// wraps sprites on both axes
+fn vip() -> CPU {
+    CPU::with_quirks(Profile::CosmacVip.quirks())
+}
+
+// the 0 glyph, F0 90 90 90 F0, with its top-left corner at (x, y)
+fn zero_at(x: u8, y: u8) -> Assembler {
+    Assembler::new()
+        .ld(V0, x)
+        .ld(V1, y)
+        .ld(V2, 0)
+        .ld_f(V2)
+        .draw(V0, V1, 5)
+}
+
+#[test]
+fn sprites_wrap_around_the_bottom_right_corner() {
+    let cpu = run_program_on(vip(), &zero_at(63, 31).halt().assemble().unwrap());
+
+    // the top row lands on the last line and spills over to the left edge
+    for x in [63, 0, 1, 2] {
+        assert!(cpu.pixel(x, 31), "({}, 31)", x);
+    }
+    // the other four rows wrap to the top
+    for y in 0..3 {
+        assert!(cpu.pixel(63, y));
+        assert!(!cpu.pixel(0, y));
+        assert!(!cpu.pixel(1, y));
+        assert!(cpu.pixel(2, y));
+    }
+    for x in [63, 0, 1, 2] {
+        assert!(cpu.pixel(x, 3), "({}, 3)", x);
+    }
+    assert_eq!(cpu.register(VF), 0);
+}
+
+#[test]
+fn wrapped_pixels_collide() {
+    let program = zero_at(63, 31).draw(V0, V1, 5).halt().assemble().unwrap();
+    let cpu = run_program_on(vip(), &program);
+
+    assert!(!cpu.pixel(63, 31));
+    assert!(!cpu.pixel(2, 0));
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn the_origin_wraps_on_every_profile() {
+    let cpu = run_program_on(
+        CPU::new(),
+        &zero_at(64 + 3, 32 + 1).halt().assemble().unwrap(),
+    );
+
+    assert!(cpu.pixel(3, 1));
+    assert!(cpu.pixel(6, 1));
+    assert!(!cpu.pixel(7, 1));
+}
+
+#[test]
+fn pixels_read_back_after_a_draw() {
+    let cpu = run_program_on(CPU::new(), &zero_at(10, 4).halt().assemble().unwrap());
+
+    // second row of the glyph is 1001
+    assert!(cpu.pixel(10, 5));
+    assert!(!cpu.pixel(11, 5));
+    assert!(!cpu.pixel(12, 5));
+    assert!(cpu.pixel(13, 5));
+
+    let rows: Vec<&[bool]> = cpu.framebuffer().collect();
+    assert_eq!(rows.len(), 32);
+    assert!(rows.iter().all(|row| row.len() == 64));
+    assert!(rows[4][10..14].iter().all(|&on| on));
+    assert_eq!(cpu.framebuffer().flatten().filter(|&&on| on).count(), 14);
+}
+
+#[test]
+fn pixels_outside_the_display_are_off() {
+    let cpu = run_program_on(vip(), &zero_at(60, 28).halt().assemble().unwrap());
+
+    assert!(cpu.pixel(63, 31));
+    assert!(!cpu.pixel(64, 28));
+    assert!(!cpu.pixel(60, 32));
+    assert!(!cpu.pixel(usize::MAX, usize::MAX));
+}
+
+fn lit_pixels(cpu: &CPU) -> usize {
+    cpu.framebuffer().flatten().filter(|&&on| on).count()
+}
+
+fn big_sprite(hires: bool) -> Vec<u8> {
+    let asm = if hires {
+        Assembler::new().hires()
+    } else {
+        Assembler::new()
+    };
+
+    asm.ld(V0, 100)
+        .ld(V1, 40)
+        .ld_i_label("sprite")
+        .draw(V0, V1, 0)
+        .halt()
+        .label("sprite")
+        .db(&[0xFF; 32])
+        .assemble()
+        .unwrap()
+}
+
+#[test]
+fn dxy0_draws_16x16_in_hires() {
+    let cpu = run_program_on(CPU::new(), &big_sprite(true));
+
+    assert_eq!(lit_pixels(&cpu), 16 * 16);
+    assert!(cpu.pixel(100, 40));
+    assert!(cpu.pixel(115, 55));
+    assert!(!cpu.pixel(116, 40));
+    assert!(!cpu.pixel(100, 56));
+}
+
+#[test]
+fn dxy0_draws_nothing_in_lores() {
+    let cpu = run_program_on(CPU::new(), &big_sprite(false));
+
+    assert_eq!(lit_pixels(&cpu), 0);
+}
+
+#[test]
+fn the_framebuffer_follows_the_resolution() {
+    let mut cpu = CPU::boot(&Assembler::new().hires().lores().assemble().unwrap()).unwrap();
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.framebuffer().count(), 64);
+    assert!(cpu.framebuffer().all(|row| row.len() == 128));
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.framebuffer().count(), 32);
+    assert!(cpu.framebuffer().all(|row| row.len() == 64));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn draws_emit_pixel_changes() {
+    use clayton_cpu::CpuEvent;
+
+    let mut cpu = CPU::boot(&zero_at(10, 4).assemble().unwrap()).unwrap();
+    let events = cpu.events();
+
+    cpu.run_with_limit(5).unwrap();
+
+    let pixels: Vec<CpuEvent> = events
+        .try_iter()
+        .filter(|event| matches!(event, CpuEvent::PixelChanged { .. }))
+        .collect();
+    assert_eq!(pixels.len(), 14);
+    assert_eq!(
+        pixels[0],
+        CpuEvent::PixelChanged {
+            x: 10,
+            y: 4,
+            on: true
+        }
+    );
+}
+
+fn wrapping(wrap_sprites: bool) -> CPU {
+    CPU::with_quirks(Quirks {
+        wrap_sprites,
+        ..Quirks::default()
+    })
+}
+
+#[test]
+fn sprites_at_x_62_wrap_or_clip() {
+    let program = zero_at(62, 0).halt().assemble().unwrap();
+
+    let wrapped = run_program_on(wrapping(true), &program);
+    assert!(wrapped.pixel(62, 0) && wrapped.pixel(63, 0));
+    assert!(wrapped.pixel(0, 0) && wrapped.pixel(1, 0));
+    // 1001 puts the last pixel of the middle rows on x = 1
+    assert!(wrapped.pixel(1, 2));
+
+    let clipped = run_program_on(wrapping(false), &program);
+    assert!(clipped.pixel(62, 0) && clipped.pixel(63, 0));
+    assert!(!clipped.pixel(0, 0) && !clipped.pixel(1, 0));
+    assert!(!clipped.pixel(1, 2));
+    assert_eq!(lit_pixels(&clipped), 7);
+}
+
+#[test]
+fn clipped_pixels_never_collide() {
+    // a 0 at the left edge first, under the columns that would wrap
+    let program = zero_at(0, 0)
+        .ld(V0, 62)
+        .draw(V0, V1, 5)
+        .halt()
+        .assemble()
+        .unwrap();
+
+    assert_eq!(run_program_on(wrapping(true), &program).register(VF), 1);
+    assert_eq!(run_program_on(wrapping(false), &program).register(VF), 0);
+}
+
+#[test]
+fn pbm_of_a_known_sprite() {
+    let cpu = run_program_on(CPU::new(), &zero_at(8, 1).halt().assemble().unwrap());
+
+    // 8 bytes per row, the glyph fills the second byte of rows 1 to 5
+    let mut expected = b"P4\n64 32\n".to_vec();
+    let mut rows = [[0u8; 8]; 32];
+    for (row, byte) in rows[1..6].iter_mut().zip([0xF0, 0x90, 0x90, 0x90, 0xF0]) {
+        row[1] = byte;
+    }
+    expected.extend(rows.iter().flatten());
+
+    assert_eq!(cpu.display_to_pbm(), expected);
+}
+
+fn clipping_y(clip_sprite_y: bool) -> CPU {
+    CPU::with_quirks(Quirks {
+        clip_sprite_y,
+        ..Quirks::default()
+    })
+}
+
+// a diagonal, one pixel per row, drawn at (0, 30) so two rows hang off the bottom.
+// (2, 0) is lit beforehand, right under the third row if it wraps
+fn diagonal_at_y_30() -> Vec<u8> {
+    Assembler::new()
+        .ld(V0, 0)
+        .ld(V1, 30)
+        .ld(V2, 2)
+        .ld_i_label("dot")
+        .draw(V2, V0, 1)
+        .ld_i_label("diagonal")
+        .draw(V0, V1, 4)
+        .halt()
+        .label("dot")
+        .db(&[0x80])
+        .label("diagonal")
+        .db(&[0x80, 0x40, 0x20, 0x10])
+        .assemble()
+        .unwrap()
+}
+
+#[test]
+fn tall_sprites_wrap_to_the_top() {
+    let cpu = run_program_on(clipping_y(false), &diagonal_at_y_30());
+
+    assert!(cpu.pixel(0, 30));
+    assert!(cpu.pixel(1, 31));
+    // the third row lands on the dot and erases it
+    assert!(!cpu.pixel(2, 0));
+    assert!(cpu.pixel(3, 1));
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn tall_sprites_clip_at_the_bottom() {
+    let cpu = run_program_on(clipping_y(true), &diagonal_at_y_30());
+
+    assert!(cpu.pixel(0, 30));
+    assert!(cpu.pixel(1, 31));
+    // the dot survives and the fourth row is nowhere
+    assert!(cpu.pixel(2, 0));
+    assert!(!cpu.pixel(3, 1));
+    assert_eq!(lit_pixels(&cpu), 3);
+    assert_eq!(cpu.register(VF), 0);
+}
+
+#[test]
+fn planes_draw_and_read_back_separately() {
+    // the 0 on plane 0 at x = 0, then on plane 1 at x = 2
+    let program = Assembler::new()
+        .ld(V0, 0)
+        .ld(V1, 2)
+        .ld_f(V0)
+        .plane(1)
+        .draw(V0, V0, 5)
+        .plane(2)
+        .draw(V1, V0, 5)
+        .halt()
+        .assemble()
+        .unwrap();
+    let cpu = run_program_on(CPU::with_quirks(Profile::XoChip.quirks()), &program);
+
+    assert!(cpu.plane_pixel(0, 0, 0) && !cpu.plane_pixel(1, 0, 0));
+    assert!(cpu.plane_pixel(0, 3, 0) && cpu.plane_pixel(1, 3, 0));
+    assert!(!cpu.plane_pixel(0, 5, 0) && cpu.plane_pixel(1, 5, 0));
+    assert_eq!(cpu.pixel_color(0, 0), 1);
+    assert_eq!(cpu.pixel_color(3, 0), 3);
+    assert_eq!(cpu.pixel_color(5, 0), 2);
+    // the middle rows only light the edges, so (1, 1) is off on both
+    assert_eq!(cpu.pixel_color(1, 1), 0);
+}
+
+#[test]
+fn monochrome_has_a_single_plane() {
+    let cpu = run_program_on(CPU::new(), &zero_at(0, 0).halt().assemble().unwrap());
+
+    assert!(cpu.plane_pixel(0, 0, 0));
+    assert!(!cpu.plane_pixel(1, 0, 0));
+    assert_eq!(cpu.pixel_color(0, 0), 1);
+    assert_eq!(cpu.pixel_color(1, 1), 0);
+}
+
+// runs everything up to the last Dxyn, asks would_collide and then draws. returns
+// the prediction once it matched VF
+fn predict_last_draw(mut cpu: CPU, asm: Assembler, n: u8) -> bool {
+    let program = asm.assemble().unwrap();
+    cpu.load_program(&program, PROGRAM_START).unwrap();
+    cpu.run_with_limit(program.len() / 2 - 1).unwrap();
+
+    let predicted = cpu.would_collide(V0, V1, n);
+    cpu.step().unwrap();
+
+    assert_eq!(predicted, cpu.register(VF) != 0);
+    predicted
+}
+
+#[test]
+fn would_collide_matches_wrapped_draws() {
+    let asm = zero_at(63, 31).draw(V0, V1, 5);
+
+    assert!(predict_last_draw(vip(), asm, 5));
+}
+
+#[test]
+fn would_collide_matches_clipped_draws() {
+    let asm = || zero_at(0, 0).ld(V0, 62).draw(V0, V1, 5);
+
+    assert!(predict_last_draw(wrapping(true), asm(), 5));
+    assert!(!predict_last_draw(wrapping(false), asm(), 5));
+}
+
+// the 0 glyph at (0, y) in hires
+fn hires_zero_at(y: u8) -> Assembler {
+    Assembler::new()
+        .hires()
+        .ld(V0, 0)
+        .ld(V1, y)
+        .ld_f(V0)
+        .draw(V0, V1, 5)
+}
+
+#[test]
+fn would_collide_counts_clipped_rows_in_hires() {
+    let row_count = || {
+        CPU::with_quirks(Quirks {
+            vf_collision_mode: CollisionMode::RowCount,
+            clip_sprite_y: true,
+            ..Quirks::default()
+        })
+    };
+
+    // nothing to hit, but three rows hang off the bottom
+    assert!(predict_last_draw(row_count(), hires_zero_at(62), 5));
+    assert!(!predict_last_draw(row_count(), hires_zero_at(10), 5));
+}
+
+fn schip() -> CPU {
+    CPU::with_quirks(Profile::SuperChip.quirks())
+}
+
+#[test]
+fn hires_vf_counts_clipped_and_colliding_rows() {
+    // three of the five rows hang off the bottom
+    let cpu = run_program_on(schip(), &hires_zero_at(62).halt().assemble().unwrap());
+    assert_eq!(cpu.register(VF), 3);
+
+    // the second draw also hits both visible rows
+    let program = hires_zero_at(62).draw(V0, V1, 5).halt().assemble().unwrap();
+    let cpu = run_program_on(schip(), &program);
+    assert_eq!(cpu.register(VF), 2 + 3);
+}
+
+#[test]
+fn lores_vf_stays_boolean_on_the_same_profile() {
+    let cpu = run_program_on(schip(), &zero_at(0, 30).halt().assemble().unwrap());
+    assert_eq!(cpu.register(VF), 0);
+
+    let program = zero_at(0, 30).draw(V0, V1, 5).halt().assemble().unwrap();
+    let cpu = run_program_on(schip(), &program);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn draw_bytes_draws_a_digit_from_a_slice() {
+    let mut cpu = CPU::new();
+    cpu.set_register(VF, 0x42);
+
+    // the 1 glyph, 20 60 20 20 70
+    cpu.draw_bytes(10, 4, &[0x20, 0x60, 0x20, 0x20, 0x70]);
+
+    assert!(cpu.pixel(12, 4));
+    assert!(cpu.pixel(11, 5) && cpu.pixel(12, 5));
+    assert!(cpu.pixel(12, 6) && cpu.pixel(12, 7));
+    assert!(cpu.pixel(11, 8) && cpu.pixel(12, 8) && cpu.pixel(13, 8));
+    assert_eq!(lit_pixels(&cpu), 8);
+    // I and VF are left alone
+    assert_eq!(cpu.index(), 0);
+    assert_eq!(cpu.register(VF), 0x42);
+
+    // drawing it again erases it
+    cpu.draw_bytes(10, 4, &[0x20, 0x60, 0x20, 0x20, 0x70]);
+    assert_eq!(lit_pixels(&cpu), 0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn ansi_snapshot_of_a_known_pattern() {
+    use clayton_cpu::{Color, RenderConfig};
+
+    let mut cpu = CPU::new();
+    cpu.draw_bytes(0, 0, &[0xA0]);
+    let config = RenderConfig {
+        fg: Color::Rgb { r: 255, g: 0, b: 0 },
+        bg: Color::Rgb { r: 0, g: 0, b: 255 },
+        on_char: '#',
+        off_char: '.',
+        ..RenderConfig::default()
+    };
+
+    let mut out = Vec::new();
+    cpu.render_rect_to(&mut out, &config, (0, 0, 4, 2)).unwrap();
+
+    // one color switch per run, the cursor moves to the start of every row
+    let red = "\x1b[38;2;255;0;0m";
+    let blue = "\x1b[38;2;0;0;255m";
+    let expected = format!(
+        "\x1b[1;1H{red}#{blue}.{red}#{blue}.\x1b[2;1H{blue}....\x1b[0m",
+        red = red,
+        blue = blue
+    );
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+}
+
+#[test]
+fn dirty_rect_tracks_changes_until_read() {
+    let mut cpu = CPU::new();
+    assert_eq!(cpu.take_dirty_rect(), None);
+
+    cpu.draw_bytes(5, 7, &[0x80]);
+    assert_eq!(cpu.take_dirty_rect(), Some((5, 7, 1, 1)));
+    // reading it cleared it
+    assert_eq!(cpu.take_dirty_rect(), None);
+
+    // grows to cover both draws
+    cpu.draw_bytes(5, 7, &[0x80]);
+    cpu.draw_bytes(10, 4, &[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+    assert_eq!(cpu.take_dirty_rect(), Some((5, 4, 9, 5)));
+}
+
+#[test]
+fn cls_dirties_the_whole_screen() {
+    let program = Assembler::new().cls().assemble().unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.take_dirty_rect(), Some((0, 0, 64, 32)));
+    assert_eq!(cpu.take_dirty_rect(), None);
+}
+
+#[test]
+fn collisions_are_counted_with_the_last_position() {
+    // the 0 three times at (10, 4), then once at (12, 5) over the third
+    let program = zero_at(10, 4)
+        .draw(V0, V1, 5)
+        .draw(V0, V1, 5)
+        .ld(V0, 12)
+        .ld(V1, 5)
+        .draw(V0, V1, 5)
+        .halt()
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    assert_eq!(cpu.collision_count(), 0);
+    assert_eq!(cpu.last_collision(), None);
+
+    // up to the first draw, then the second erases it
+    cpu.run_with_limit(5).unwrap();
+    assert_eq!(cpu.collision_count(), 0);
+    cpu.step().unwrap();
+    assert_eq!(cpu.collision_count(), 1);
+    assert_eq!(cpu.last_collision(), Some((10, 4)));
+
+    // the third lands on a blank screen
+    cpu.step().unwrap();
+    assert_eq!(cpu.collision_count(), 1);
+
+    cpu.run_with_limit(3).unwrap();
+    assert_eq!(cpu.register(VF), 1);
+    assert_eq!(cpu.collision_count(), 2);
+    assert_eq!(cpu.last_collision(), Some((12, 5)));
+}
+
+#[test]
+fn clipped_rows_alone_count_as_a_collision() {
+    let program = hires_zero_at(62).halt().assemble().unwrap();
+    let cpu = run_program_on(schip(), &program);
+
+    assert_eq!(cpu.register(VF), 3);
+    assert_eq!(cpu.collision_count(), 1);
+    assert_eq!(cpu.last_collision(), Some((0, 62)));
+}
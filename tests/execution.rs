@@ -0,0 +1,132 @@
+use clayton_cpu::Register::*;
+use clayton_cpu::{Assembler, CpuError, Profile, StepOutcome, CPU, MEMORY_SIZE, PROGRAM_START};
+
+#[test]
+fn step_runs_one_instruction_at_a_time() {
+    let program = Assembler::new().ld(V0, 5).ld(V1, 10).assemble().unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+
+    assert_eq!(cpu.step(), Ok(StepOutcome::Continue));
+    assert_eq!(cpu.register(V0), 5);
+    assert_eq!(cpu.register(V1), 0);
+    assert_eq!(cpu.pc(), PROGRAM_START + 2);
+
+    assert_eq!(cpu.step(), Ok(StepOutcome::Continue));
+    assert_eq!(cpu.register(V1), 10);
+    assert_eq!(cpu.pc(), PROGRAM_START + 4);
+}
+
+#[test]
+fn run_with_limit_stops_a_self_jump() {
+    let mut cpu = CPU::boot(&[0x12, 0x00]).unwrap();
+
+    assert_eq!(cpu.run_with_limit(100), Ok(100));
+    assert_eq!(cpu.pc(), PROGRAM_START);
+    assert_eq!(cpu.cycle_count(), 100);
+}
+
+#[test]
+fn jumping_to_the_last_byte_fails_gracefully() {
+    let mut cpu = CPU::boot(&Assembler::new().jmp(0xFFF).assemble().unwrap()).unwrap();
+
+    assert_eq!(cpu.step(), Err(CpuError::PcOutOfBounds(MEMORY_SIZE - 1)));
+}
+
+#[test]
+fn fetching_from_the_last_byte_fails_gracefully() {
+    let mut cpu = CPU::builder().start_address(MEMORY_SIZE - 1).build();
+
+    assert_eq!(cpu.step(), Err(CpuError::PcOutOfBounds(MEMORY_SIZE - 1)));
+}
+
+// a jump into the middle of a word, where 60 07 is LD V0, 7
+fn odd_jump() -> Vec<u8> {
+    Assembler::new()
+        .jmp(0x203)
+        .db(&[0x00, 0x60, 0x07])
+        .assemble()
+        .unwrap()
+}
+
+#[test]
+fn strict_alignment_refuses_an_odd_pc() {
+    let mut cpu = CPU::boot(&odd_jump()).unwrap();
+    cpu.set_strict_alignment(true);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.step(), Err(CpuError::MisalignedPc(0x203)));
+    assert_eq!(cpu.register(V0), 0);
+}
+
+#[test]
+fn an_odd_pc_runs_without_strict_alignment() {
+    let mut cpu = CPU::boot(&odd_jump()).unwrap();
+
+    assert_eq!(cpu.run_with_limit(2), Ok(2));
+    assert_eq!(cpu.register(V0), 7);
+    assert_eq!(cpu.pc(), 0x205);
+}
+
+// 0xFF8 + 0x10 lands past the end of memory
+fn far_jump() -> Vec<u8> {
+    Assembler::new()
+        .ld(V0, 0x10)
+        .jmp_v0(0xFF8)
+        .assemble()
+        .unwrap()
+}
+
+#[test]
+fn out_of_range_jumps_fail_by_default() {
+    let mut cpu = CPU::boot(&far_jump()).unwrap();
+
+    assert_eq!(cpu.run_with_limit(2), Err(CpuError::PcOutOfBounds(0x1008)));
+
+    let mut cpu = CPU::boot(&Assembler::new().call(0xFFF).assemble().unwrap()).unwrap();
+    assert_eq!(cpu.step(), Err(CpuError::PcOutOfBounds(0xFFF)));
+    assert!(cpu.call_stack().is_empty());
+}
+
+#[test]
+fn out_of_range_jumps_wrap_when_asked() {
+    let mut cpu = CPU::boot(&far_jump()).unwrap();
+    cpu.set_wrap_jumps(true);
+
+    assert_eq!(cpu.run_with_limit(2), Ok(2));
+    // over the 0xFFF addresses an opcode can start at
+    assert_eq!(cpu.pc(), 0x1008 % 0xFFF);
+
+    let mut cpu = CPU::boot(&Assembler::new().call(0xFFF).assemble().unwrap()).unwrap();
+    cpu.set_wrap_jumps(true);
+    assert_eq!(cpu.step(), Ok(StepOutcome::Continue));
+    assert_eq!(cpu.pc(), 0);
+    assert_eq!(cpu.call_stack(), [PROGRAM_START as u16 + 2]);
+}
+
+// the outcome of every step of two draws with other instructions around them
+fn draw_outcomes(mut cpu: CPU) -> Vec<StepOutcome> {
+    let program = Assembler::new()
+        .ld_f(V0)
+        .draw(V0, V0, 5)
+        .ld(V1, 8)
+        .draw(V1, V0, 5)
+        .ld(V2, 1)
+        .assemble()
+        .unwrap();
+    cpu.load_program(&program, PROGRAM_START).unwrap();
+
+    (0..5).map(|_| cpu.step().unwrap()).collect()
+}
+
+#[test]
+fn display_wait_signals_once_per_draw() {
+    use StepOutcome::*;
+
+    let vip = CPU::with_quirks(Profile::CosmacVip.quirks());
+    assert_eq!(
+        draw_outcomes(vip),
+        [Continue, AwaitingVBlank, Continue, AwaitingVBlank, Continue]
+    );
+
+    assert_eq!(draw_outcomes(CPU::new()), [Continue; 5]);
+}
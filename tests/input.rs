@@ -0,0 +1,142 @@
+use clayton_cpu::Register::*;
+use clayton_cpu::{
+    map_key, Assembler, InputConfig, KeyEvent, KeyTracker, RecordedInput, CPU, PROGRAM_START,
+};
+use std::time::Duration;
+
+fn read_key_cpu() -> CPU {
+    let program = Assembler::new().read_key(V3).assemble().unwrap();
+
+    CPU::boot(&program).unwrap()
+}
+
+#[test]
+fn read_key_waits_without_blocking() {
+    let mut cpu = read_key_cpu();
+
+    assert_eq!(cpu.run_with_limit(5), Ok(5));
+    assert_eq!(cpu.pc(), PROGRAM_START);
+}
+
+#[test]
+fn read_key_latches_on_release() {
+    let mut cpu = read_key_cpu();
+
+    cpu.set_key(0xB, true);
+    cpu.step().unwrap();
+    // still held, Fx0A keeps stalling
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), PROGRAM_START);
+
+    cpu.set_key(0xB, false);
+    cpu.step().unwrap();
+    assert_eq!(cpu.register(V3), 0xB);
+    assert_eq!(cpu.pc(), PROGRAM_START + 2);
+}
+
+#[test]
+fn keyboard_layout() {
+    let table = [
+        ('1', 0x1),
+        ('2', 0x2),
+        ('3', 0x3),
+        ('4', 0xC),
+        ('q', 0x4),
+        ('w', 0x5),
+        ('e', 0x6),
+        ('r', 0xD),
+        ('a', 0x7),
+        ('s', 0x8),
+        ('d', 0x9),
+        ('f', 0xE),
+        ('z', 0xA),
+        ('x', 0x0),
+        ('c', 0xB),
+        ('v', 0xF),
+    ];
+
+    for (c, key) in table {
+        assert_eq!(map_key(c), Some(key), "{}", c);
+        assert_eq!(map_key(c.to_ascii_uppercase()), Some(key), "{}", c);
+    }
+}
+
+#[test]
+fn unmapped_keys_are_ignored() {
+    for c in ['5', 't', 'g', 'b', '0', ' ', '\n', 'é'] {
+        assert_eq!(map_key(c), None, "{:?}", c);
+    }
+}
+
+#[test]
+fn recorded_input_replays_a_script() {
+    // 5 goes down while Fx0A waits and comes back up two steps later, then A is
+    // held over the Ex9E
+    let script = vec![(2, 0x5, true), (4, 0x5, false), (6, 0xA, true)];
+    let program = Assembler::new()
+        .read_key(V3)
+        .ld(V0, 0xA)
+        .skip_if_key(V0)
+        .ld(V1, 1)
+        .ld(V2, 1)
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program)
+        .unwrap()
+        .with_input(Box::new(RecordedInput::new(script)));
+
+    assert_eq!(cpu.run_with_limit(8), Ok(8));
+
+    assert_eq!(cpu.register(V3), 0x5);
+    assert_eq!(cpu.register(V1), 0);
+    assert_eq!(cpu.register(V2), 1);
+    assert_eq!(cpu.pc(), PROGRAM_START + 10);
+}
+
+fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+#[test]
+fn key_tracker_releases_after_the_timeout() {
+    let mut tracker = KeyTracker::new(InputConfig {
+        release_timeout: ms(50),
+    });
+
+    assert_eq!(
+        tracker.press(0x4, ms(0)),
+        Some(KeyEvent {
+            key: 0x4,
+            pressed: true
+        })
+    );
+    assert_eq!(tracker.expire(ms(49)), []);
+    assert_eq!(
+        tracker.expire(ms(50)),
+        [KeyEvent {
+            key: 0x4,
+            pressed: false
+        }]
+    );
+    // already released
+    assert_eq!(tracker.expire(ms(100)), []);
+}
+
+#[test]
+fn key_tracker_ignores_auto_repeat() {
+    let mut tracker = KeyTracker::new(InputConfig {
+        release_timeout: ms(50),
+    });
+
+    assert!(tracker.press(0x4, ms(0)).is_some());
+    // repeats only push the release back
+    assert_eq!(tracker.press(0x4, ms(30)), None);
+    assert_eq!(tracker.press(0x4, ms(60)), None);
+    assert_eq!(tracker.expire(ms(100)), []);
+    assert_eq!(tracker.expire(ms(110)).len(), 1);
+
+    // a press after the release is a new one
+    assert!(tracker.press(0x4, ms(120)).is_some());
+    // and there is no key 0x10
+    assert_eq!(tracker.press(0x10, ms(120)), None);
+}
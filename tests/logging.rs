@@ -0,0 +1,118 @@
+use clayton_cpu::{CpuError, HaltPolicy, CPU, PROGRAM_START};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::RefCell;
+use std::sync::Once;
+
+// tests run on parallel threads, so every thread keeps its own records
+thread_local! {
+    static RECORDS: RefCell<Vec<(Level, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let entry = (record.level(), record.args().to_string());
+        RECORDS.with(|records| records.borrow_mut().push(entry));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger;
+static INIT: Once = Once::new();
+
+// runs `f` and returns what it logged
+fn capture(f: impl FnOnce()) -> Vec<(Level, String)> {
+    INIT.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+    });
+
+    RECORDS.with(|records| records.borrow_mut().clear());
+    f();
+    RECORDS.with(|records| records.borrow_mut().drain(..).collect())
+}
+
+fn warnings(records: &[(Level, String)]) -> Vec<&str> {
+    records
+        .iter()
+        .filter(|(level, _)| *level == Level::Warn)
+        .map(|(_, message)| message.as_str())
+        .collect()
+}
+
+#[test]
+fn loading_over_the_font_warns() {
+    let records = capture(|| {
+        CPU::new().load_program(&[0x12, 0x00], 0x000).unwrap();
+    });
+
+    assert_eq!(
+        warnings(&records),
+        ["loading at 0x0000 overwrites the font area below 0x0104"]
+    );
+}
+
+#[test]
+fn loading_at_program_start_does_not_warn() {
+    let records = capture(|| {
+        CPU::new()
+            .load_program(&[0x12, 0x00], PROGRAM_START)
+            .unwrap();
+    });
+
+    assert!(warnings(&records).is_empty(), "{:?}", records);
+}
+
+#[test]
+fn protected_font_refuses_the_load() {
+    let mut cpu = CPU::new();
+    cpu.set_protect_font(true);
+
+    assert_eq!(
+        cpu.load_program(&[0x12, 0x00], 0x000),
+        Err(CpuError::FontOverlap(0x000))
+    );
+}
+
+#[test]
+fn protected_font_refuses_segments_before_writing_any() {
+    let mut cpu = CPU::new();
+    cpu.set_protect_font(true);
+
+    let segments: [(usize, &[u8]); 2] = [(0x300, &[0xAA]), (0x010, &[0xBB])];
+    assert_eq!(
+        cpu.load_segments(&segments),
+        Err(CpuError::FontOverlap(0x010))
+    );
+    assert_eq!(cpu.memory()[0x300], 0);
+}
+
+#[test]
+fn unknown_opcodes_warn() {
+    let mut cpu = CPU::boot(&[0x5A, 0xB1]).unwrap();
+    let mut result = Ok(());
+
+    let records = capture(|| result = cpu.step().map(|_| ()));
+
+    assert_eq!(result, Err(CpuError::UnknownOpcode(0x5AB1)));
+    assert_eq!(warnings(&records), ["unknown opcode 5ab1 at 0x0200"]);
+}
+
+#[test]
+fn halting_logs_at_info() {
+    let mut cpu = CPU::boot(&[0x00, 0x00]).unwrap();
+    cpu.set_zero_opcode_policy(HaltPolicy::Halt);
+
+    let records = capture(|| cpu.run().unwrap());
+
+    assert_eq!(
+        records,
+        [(Level::Info, "terminating execution at 0x0200".to_string())]
+    );
+}
@@ -0,0 +1,211 @@
+use clayton_cpu::testing::run_program_on;
+use clayton_cpu::Register::*;
+use clayton_cpu::{Assembler, ByteOrder, CpuError, MmioDevice, CPU, PROGRAM_START};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn fonts_at_zero() {
+    let program = Assembler::new()
+        .ld(V0, 0xA)
+        .ld_f(V0)
+        .halt()
+        .assemble()
+        .unwrap();
+    let cpu = run_program_on(CPU::builder().font_base(0x000).build(), &program);
+
+    assert_eq!(cpu.index(), 0xA * 5);
+    // the top row of A
+    assert_eq!(cpu.memory()[0xA * 5], 0xF0);
+}
+
+#[test]
+fn set_font_base_moves_the_fonts() {
+    let mut cpu = CPU::new();
+
+    assert_eq!(cpu.set_font_base(0x000), Ok(()));
+    assert_eq!(&cpu.memory()[..5], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+}
+
+#[test]
+fn font_base_past_the_interpreter_area_is_refused() {
+    let mut cpu = CPU::new();
+
+    assert_eq!(
+        cpu.set_font_base(0xFF0),
+        Err(CpuError::FontOutOfRange(0xFF0))
+    );
+    assert_eq!(
+        cpu.set_font_base(usize::MAX),
+        Err(CpuError::FontOutOfRange(usize::MAX))
+    );
+}
+
+#[test]
+#[should_panic(expected = "doesn't fit below")]
+fn building_with_a_font_past_the_interpreter_area_panics() {
+    CPU::builder().font_base(0x1000).build();
+}
+
+#[test]
+fn a_rom_past_the_end_of_memory_is_refused() {
+    let mut cpu = CPU::new();
+    // 3.8KB from 0x200 ends 216 bytes past 0x1000
+    let rom = vec![0x12; 3800];
+
+    let err = cpu.load_program(&rom, PROGRAM_START).unwrap_err();
+
+    assert_eq!(err, CpuError::ProgramTooLarge(216));
+    assert_eq!(err.to_string(), "program overflows memory by 216 bytes");
+    assert_eq!(cpu.memory()[PROGRAM_START], 0);
+}
+
+#[test]
+fn a_start_address_that_overflows_is_refused() {
+    let mut cpu = CPU::new();
+
+    assert_eq!(
+        cpu.load_program(&[0x12, 0x00], usize::MAX),
+        Err(CpuError::AddressOutOfBounds(usize::MAX))
+    );
+}
+
+#[test]
+fn a_64kb_cpu_runs_a_rom_at_program_start() {
+    let program = Assembler::new()
+        .ld(V0, 0x42)
+        .ld_i(0x300)
+        .store_regs(V0)
+        .halt()
+        .assemble()
+        .unwrap();
+    let cpu = run_program_on(CPU::with_memory_size(0x10000), &program);
+
+    assert_eq!(cpu.memory().len(), 0x10000);
+    assert_eq!(cpu.memory()[0x300], 0x42);
+    assert_eq!(cpu.pc(), PROGRAM_START + 8);
+}
+
+#[test]
+fn a_64kb_cpu_loads_past_4kb() {
+    let mut cpu = CPU::with_memory_size(0x10000);
+
+    assert_eq!(cpu.load_program(&[0x12, 0x34], 0xF000), Ok(()));
+    assert_eq!(cpu.memory()[0xF001], 0x34);
+    assert_eq!(
+        cpu.load_program(&[0x12, 0x34], 0xFFFF),
+        Err(CpuError::ProgramTooLarge(1))
+    );
+}
+
+#[test]
+fn segments_load_at_their_addresses() {
+    let mut cpu = CPU::new();
+    let segments: [(usize, &[u8]); 2] = [(0x300, &[0xAA, 0xBB]), (0x200, &[0x12, 0x00])];
+
+    assert_eq!(cpu.load_segments(&segments), Ok(()));
+    assert_eq!(cpu.memory()[0x200..0x202], [0x12, 0x00]);
+    assert_eq!(cpu.memory()[0x300..0x302], [0xAA, 0xBB]);
+}
+
+#[test]
+fn overlapping_segments_are_refused() {
+    let mut cpu = CPU::new();
+    let segments: [(usize, &[u8]); 2] = [(0x300, &[0xAA, 0xBB]), (0x301, &[0xCC])];
+
+    assert_eq!(
+        cpu.load_segments(&segments),
+        Err(CpuError::SegmentOverlap(0x301))
+    );
+    // nothing is written
+    assert_eq!(cpu.memory()[0x300], 0);
+}
+
+#[test]
+fn a_segment_that_overflows_is_refused() {
+    let mut cpu = CPU::new();
+    let segments: [(usize, &[u8]); 2] = [(0x300, &[0xAA]), (usize::MAX, &[0xBB])];
+
+    assert_eq!(
+        cpu.load_segments(&segments),
+        Err(CpuError::AddressOutOfBounds(usize::MAX))
+    );
+    assert_eq!(cpu.memory()[0x300], 0);
+}
+
+// reads back 0x99 and records every write
+#[derive(Clone, Default)]
+struct Port(Rc<RefCell<Vec<(usize, u8)>>>);
+
+impl MmioDevice for Port {
+    fn read(&self, _addr: usize) -> u8 {
+        0x99
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        self.0.borrow_mut().push((addr, value));
+    }
+}
+
+#[test]
+fn a_device_at_0xeff_sees_fx55_and_fx65() {
+    let port = Port::default();
+    let program = Assembler::new()
+        .ld(V0, 0x10)
+        .ld(V1, 0x11)
+        .ld(V2, 0x12)
+        .ld_i(0xEFD)
+        .store_regs(V2)
+        .ld_i(0xEFF)
+        .load_regs(V0)
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.attach_device(0xEFF, Box::new(port.clone()));
+
+    cpu.run_with_limit(5).unwrap();
+    assert_eq!(*port.0.borrow(), [(0xEFF, 0x12)]);
+    // the bytes around it land in RAM, the device's own doesn't
+    assert_eq!(cpu.memory()[0xEFD..=0xEFF], [0x10, 0x11, 0x00]);
+
+    cpu.run_with_limit(2).unwrap();
+    assert_eq!(cpu.register(V0), 0x99);
+}
+
+#[test]
+fn little_endian_roms_are_swapped_on_load() {
+    let program = Assembler::new()
+        .ld(V0, 0x12)
+        .ld(V1, 0x34)
+        .add_xy(V0, V1)
+        .assemble()
+        .unwrap();
+    let swapped: Vec<u8> = program
+        .chunks(2)
+        .flat_map(|word| [word[1], word[0]])
+        .chain([0xAB])
+        .collect();
+
+    let mut cpu = CPU::new();
+    cpu.set_rom_byte_order(ByteOrder::LittleEndian);
+    cpu.load_program(&swapped, PROGRAM_START).unwrap();
+
+    assert_eq!(cpu.memory()[PROGRAM_START..PROGRAM_START + 6], program);
+    // the odd byte out stays where it was
+    assert_eq!(cpu.memory()[PROGRAM_START + 6], 0xAB);
+
+    cpu.run_with_limit(3).unwrap();
+    assert_eq!(cpu.register(V0), 0x46);
+}
+
+#[test]
+fn boot_places_the_rom_at_program_start() {
+    let rom = [0x60, 0x2A, 0x12, 0x02];
+    let cpu = CPU::boot(&rom).unwrap();
+
+    assert_eq!(PROGRAM_START, 0x200);
+    assert_eq!(cpu.memory()[PROGRAM_START..PROGRAM_START + rom.len()], rom);
+    assert_eq!(cpu.pc(), PROGRAM_START);
+    // nothing spills in front of it
+    assert_eq!(cpu.memory()[PROGRAM_START - 1], 0);
+}
@@ -0,0 +1,50 @@
+// sticks to what the core offers without the std feature: no terminal, no
+// channels, no save states. `cargo test --no-default-features` runs it against the
+// no_std build of the library
+use clayton_cpu::Register::*;
+use clayton_cpu::{Assembler, CpuError, HaltPolicy, StepOutcome, CPU, PROGRAM_START};
+
+#[test]
+fn the_core_steps_a_program() {
+    let program = Assembler::new()
+        .ld(V0, 3)
+        .ld_dt_vx(V0)
+        .ld_f(V0)
+        .draw(V1, V1, 5)
+        .read_key(V2)
+        .halt()
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.set_zero_opcode_policy(HaltPolicy::Halt);
+
+    for _ in 0..4 {
+        assert_eq!(cpu.step(), Ok(StepOutcome::Continue));
+    }
+    // the 3 glyph starts with F0
+    assert!((0..4).all(|x| cpu.pixel(x, 0)));
+    assert!(!cpu.pixel(4, 0));
+
+    // Fx0A stalls until a key goes down and up again
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), PROGRAM_START + 8);
+    cpu.set_key(0xC, true);
+    cpu.step().unwrap();
+    cpu.set_key(0xC, false);
+    cpu.step().unwrap();
+    assert_eq!(cpu.register(V2), 0xC);
+
+    cpu.tick_timers();
+    assert_eq!(cpu.delay_timer(), 2);
+
+    assert_eq!(cpu.run(), Ok(()));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.pc(), PROGRAM_START + 12);
+}
+
+#[test]
+fn the_core_reports_errors() {
+    let mut cpu = CPU::boot(&[0x00, 0xEE]).unwrap();
+
+    assert_eq!(cpu.step(), Err(CpuError::StackUnderflow));
+}
@@ -0,0 +1,892 @@
+// one test per arm of CPU::execute, driven through the headless harness
+use clayton_cpu::testing::{run_program, run_program_on};
+use clayton_cpu::Register::*;
+use clayton_cpu::{
+    Assembler, CpuError, Profile, Quirks, RandomSource, CPU, FONT_START, PROGRAM_START,
+};
+
+fn assemble(asm: Assembler) -> Vec<u8> {
+    asm.assemble().unwrap()
+}
+
+fn lit_pixels(cpu: &CPU) -> usize {
+    cpu.framebuffer().flatten().filter(|&&on| on).count()
+}
+
+#[test]
+fn halt_stops_after_the_zero_word() {
+    let cpu = run_program(&assemble(Assembler::new().halt().ld(V0, 1)));
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.pc(), 0x202);
+    assert_eq!(cpu.register(V0), 0);
+}
+
+#[test]
+fn nop_and_sys_are_ignored() {
+    let cpu = run_program(&assemble(
+        Assembler::new().nop().sys(0x123).ld(V0, 1).halt(),
+    ));
+
+    assert_eq!(cpu.register(V0), 1);
+    assert_eq!(cpu.pc(), 0x208);
+}
+
+#[test]
+fn nops_only_advance_the_pc() {
+    let mut cpu = CPU::boot(&assemble(Assembler::new().nop().nop().nop())).unwrap();
+
+    assert_eq!(cpu.run_with_limit(3), Ok(3));
+    assert_eq!(cpu.pc(), PROGRAM_START + 6);
+    assert!(!cpu.is_halted());
+}
+
+#[test]
+fn cls_clears_the_display() {
+    let cpu = run_program(&assemble(
+        Assembler::new().ld_f(V0).draw(V0, V0, 5).cls().halt(),
+    ));
+
+    assert_eq!(lit_pixels(&cpu), 0);
+}
+
+#[test]
+fn call_and_ret() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .call_label("sub")
+            .ld(V1, 2)
+            .halt()
+            .label("sub")
+            .ld(V0, 1)
+            .ret(),
+    ));
+
+    assert_eq!(cpu.register(V0), 1);
+    assert_eq!(cpu.register(V1), 2);
+    assert!(cpu.call_stack().is_empty());
+}
+
+#[test]
+fn the_17th_nested_call_overflows() {
+    // calls itself forever
+    let mut cpu = CPU::boot(&assemble(Assembler::new().label("f").call_label("f"))).unwrap();
+
+    assert_eq!(cpu.run_with_limit(16), Ok(16));
+    assert_eq!(cpu.call_stack().len(), 16);
+    assert_eq!(cpu.step(), Err(CpuError::StackOverflow));
+}
+
+#[test]
+fn a_deeper_stack_holds_20_calls() {
+    // each call goes to the next one, so the return addresses count up
+    let mut asm = Assembler::new();
+    for depth in 1..=20 {
+        asm = asm.call((PROGRAM_START + 2 * depth) as u16);
+    }
+    let mut cpu = CPU::builder().stack_depth(32).build();
+    cpu.load_program(&assemble(asm), PROGRAM_START).unwrap();
+
+    assert_eq!(cpu.run_with_limit(20), Ok(20));
+
+    let expected: Vec<u16> = (1..=20)
+        .map(|depth| (PROGRAM_START + 2 * depth) as u16)
+        .collect();
+    assert_eq!(cpu.call_stack(), expected);
+    assert_eq!(cpu.pc(), PROGRAM_START + 40);
+}
+
+#[test]
+fn the_33rd_nested_call_overflows_a_depth_32_stack() {
+    let mut cpu = CPU::builder().stack_depth(32).build();
+    cpu.load_program(
+        &assemble(Assembler::new().label("f").call_label("f")),
+        PROGRAM_START,
+    )
+    .unwrap();
+
+    assert_eq!(cpu.run_with_limit(32), Ok(32));
+    assert_eq!(cpu.step(), Err(CpuError::StackOverflow));
+}
+
+#[test]
+fn ret_on_an_empty_stack_underflows() {
+    let mut cpu = CPU::boot(&assemble(Assembler::new().ret())).unwrap();
+
+    assert_eq!(cpu.step(), Err(CpuError::StackUnderflow));
+}
+
+#[test]
+fn scroll_down_moves_rows() {
+    // the top row of the 0 glyph is 0xF0
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld_f(V0)
+            .draw(V0, V0, 1)
+            .scroll_down(2)
+            .halt(),
+    ));
+
+    assert!(!cpu.pixel(0, 0));
+    assert!(cpu.pixel(0, 2));
+    assert!(cpu.pixel(3, 2));
+}
+
+#[test]
+fn scroll_right_moves_four_pixels() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld_f(V0)
+            .draw(V0, V0, 1)
+            .scroll_right()
+            .halt(),
+    ));
+
+    assert!(!cpu.pixel(0, 0));
+    assert!(cpu.pixel(4, 0));
+    assert!(cpu.pixel(7, 0));
+    assert!(!cpu.pixel(8, 0));
+}
+
+#[test]
+fn scroll_left_moves_four_pixels() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld(V1, 4)
+            .ld_f(V0)
+            .draw(V1, V0, 1)
+            .scroll_left()
+            .halt(),
+    ));
+
+    assert!(cpu.pixel(0, 0));
+    assert!(cpu.pixel(3, 0));
+    assert!(!cpu.pixel(4, 0));
+}
+
+#[test]
+fn hires_and_lores_switch_resolution() {
+    let cpu = run_program(&assemble(Assembler::new().hires().halt()));
+    assert_eq!(cpu.display_size(), (128, 64));
+
+    let cpu = run_program(&assemble(Assembler::new().hires().lores().halt()));
+    assert_eq!(cpu.display_size(), (64, 32));
+}
+
+#[test]
+fn jmp_skips_over_code() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .jmp_label("end")
+            .ld(V0, 1)
+            .label("end")
+            .halt(),
+    ));
+
+    assert_eq!(cpu.register(V0), 0);
+}
+
+#[test]
+fn se_and_sne_compare_against_kk() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld(V0, 5)
+            .se(V0, 5)
+            .ld(V1, 1)
+            .se(V0, 6)
+            .ld(V2, 1)
+            .sne(V0, 6)
+            .ld(V3, 1)
+            .sne(V0, 5)
+            .ld(V4, 1)
+            .halt(),
+    ));
+
+    assert_eq!(cpu.register(V1), 0);
+    assert_eq!(cpu.register(V2), 1);
+    assert_eq!(cpu.register(V3), 0);
+    assert_eq!(cpu.register(V4), 1);
+}
+
+#[test]
+fn se_xy_and_sne_xy_compare_registers() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld(V0, 5)
+            .ld(V1, 5)
+            .se_xy(V0, V1)
+            .ld(V2, 1)
+            .sne_xy(V0, V1)
+            .ld(V3, 1)
+            .halt(),
+    ));
+
+    assert_eq!(cpu.register(V2), 0);
+    assert_eq!(cpu.register(V3), 1);
+}
+
+#[test]
+fn ld_and_ld_xy() {
+    let cpu = run_program(&assemble(
+        Assembler::new().ld(V3, 0x42).ld_xy(V7, V3).halt(),
+    ));
+
+    assert_eq!(cpu.register(V3), 0x42);
+    assert_eq!(cpu.register(V7), 0x42);
+}
+
+#[test]
+fn add_xy_sets_the_carry() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld(V0, 200)
+            .ld(V1, 100)
+            .add_xy(V0, V1)
+            .halt(),
+    ));
+
+    assert_eq!(cpu.register(V0), 44);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn sub_xy_sets_not_borrow() {
+    let cpu = run_program(&assemble(
+        Assembler::new().ld(V0, 5).ld(V1, 3).sub_xy(V0, V1).halt(),
+    ));
+
+    assert_eq!(cpu.register(V0), 2);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn subn_xy_subtracts_the_other_way() {
+    let cpu = run_program(&assemble(
+        Assembler::new().ld(V0, 3).ld(V1, 5).subn_xy(V0, V1).halt(),
+    ));
+
+    assert_eq!(cpu.register(V0), 2);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn logic_ops() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld(V0, 0b1100)
+            .ld(V1, 0b1010)
+            .ld_xy(V2, V0)
+            .ld_xy(V3, V0)
+            .and_xy(V0, V1)
+            .or_xy(V2, V1)
+            .xor_xy(V3, V1)
+            .halt(),
+    ));
+
+    assert_eq!(cpu.register(V0), 0b1000);
+    assert_eq!(cpu.register(V2), 0b1110);
+    assert_eq!(cpu.register(V3), 0b0110);
+}
+
+// VF after `op` on V0 and V1, with VF set to 1 beforehand
+fn vf_after_logic(reset_vf: bool, op: fn(Assembler) -> Assembler) -> u8 {
+    let cpu = CPU::with_quirks(Quirks {
+        logic_quirk_reset_vf: reset_vf,
+        ..Quirks::default()
+    });
+
+    let program = assemble(op(Assembler::new().ld(VF, 1).ld(V0, 0x0C).ld(V1, 0x0A)).halt());
+    run_program_on(cpu, &program).register(VF)
+}
+
+#[test]
+fn logic_ops_reset_vf_under_the_quirk() {
+    assert_eq!(vf_after_logic(true, |asm| asm.or_xy(V0, V1)), 0);
+    assert_eq!(vf_after_logic(true, |asm| asm.and_xy(V0, V1)), 0);
+    assert_eq!(vf_after_logic(true, |asm| asm.xor_xy(V0, V1)), 0);
+}
+
+#[test]
+fn logic_ops_keep_vf_without_the_quirk() {
+    assert_eq!(vf_after_logic(false, |asm| asm.or_xy(V0, V1)), 1);
+    assert_eq!(vf_after_logic(false, |asm| asm.and_xy(V0, V1)), 1);
+    assert_eq!(vf_after_logic(false, |asm| asm.xor_xy(V0, V1)), 1);
+}
+
+#[test]
+fn shr_and_shl() {
+    let cpu = run_program(&assemble(
+        Assembler::new().ld(V1, 0x81).shr_xy(V0, V1).halt(),
+    ));
+    assert_eq!(cpu.register(V0), 0x40);
+    assert_eq!(cpu.register(VF), 1);
+
+    let cpu = run_program(&assemble(
+        Assembler::new().ld(V1, 0x81).shl_xy(V0, V1).halt(),
+    ));
+    assert_eq!(cpu.register(V0), 0x02);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn shift_quirk_shifts_vx_in_place() {
+    let shifting = || {
+        CPU::with_quirks(Quirks {
+            shift_quirk: true,
+            ..Quirks::default()
+        })
+    };
+
+    let cpu = run_program_on(
+        shifting(),
+        &assemble(
+            Assembler::new()
+                .ld(V0, 0x03)
+                .ld(V1, 0x80)
+                .shr_xy(V0, V1)
+                .halt(),
+        ),
+    );
+    assert_eq!(cpu.register(V0), 0x01);
+    assert_eq!(cpu.register(V1), 0x80);
+    assert_eq!(cpu.register(VF), 1);
+
+    let cpu = run_program_on(
+        shifting(),
+        &assemble(
+            Assembler::new()
+                .ld(V0, 0xC0)
+                .ld(V1, 0x01)
+                .shl_xy(V0, V1)
+                .halt(),
+        ),
+    );
+    assert_eq!(cpu.register(V0), 0x80);
+    assert_eq!(cpu.register(V1), 0x01);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn shifts_depend_on_the_profile() {
+    let program = assemble(
+        Assembler::new()
+            .ld(V0, 0x10)
+            .ld(V1, 0x03)
+            .shr_xy(V0, V1)
+            .halt(),
+    );
+
+    let vip = run_program_on(CPU::with_quirks(Profile::CosmacVip.quirks()), &program);
+    assert_eq!(vip.register(V0), 0x01);
+    assert_eq!(vip.register(VF), 1);
+
+    let schip = run_program_on(CPU::with_quirks(Profile::SuperChip.quirks()), &program);
+    assert_eq!(schip.register(V0), 0x08);
+    assert_eq!(schip.register(VF), 0);
+}
+
+#[test]
+fn mul_and_div_are_unknown_in_standard_mode() {
+    let mut cpu = CPU::boot(&assemble(Assembler::new().mul_xy(V0, V1))).unwrap();
+    assert_eq!(cpu.step(), Err(CpuError::UnknownOpcode(0x801C)));
+
+    let mut cpu = CPU::boot(&assemble(Assembler::new().div_xy(V0, V1))).unwrap();
+    assert_eq!(cpu.step(), Err(CpuError::UnknownOpcode(0x801D)));
+}
+
+#[test]
+fn mul_xy_sets_overflow() {
+    let mut cpu = CPU::new();
+    cpu.set_standard_mode(false);
+
+    let cpu = run_program_on(
+        cpu,
+        &assemble(Assembler::new().ld(V0, 20).ld(V1, 13).mul_xy(V0, V1).halt()),
+    );
+
+    assert_eq!(cpu.register(V0), (20u16 * 13 % 256) as u8);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn div_xy_writes_quotient_and_remainder() {
+    let mut cpu = CPU::new();
+    cpu.set_standard_mode(false);
+
+    let cpu = run_program_on(
+        cpu,
+        &assemble(Assembler::new().ld(V0, 17).ld(V1, 5).div_xy(V0, V1).halt()),
+    );
+
+    assert_eq!(cpu.register(V0), 3);
+    assert_eq!(cpu.register(V1), 2);
+}
+
+#[test]
+fn div_xy_by_zero_is_an_error() {
+    let program = assemble(Assembler::new().ld(V0, 17).div_xy(V0, V1));
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.set_standard_mode(false);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.step(), Err(CpuError::DivideByZero));
+    // neither register was touched
+    assert_eq!(cpu.register(V0), 17);
+    assert_eq!(cpu.register(V1), 0);
+}
+
+// runs `op` with VF as its destination, VF = vf and V0 = v0 beforehand
+fn vf_after(op: fn(Assembler) -> Assembler, vf: u8, v0: u8) -> u8 {
+    let mut cpu = CPU::new();
+    cpu.set_standard_mode(false);
+
+    let program = assemble(op(Assembler::new().ld(VF, vf).ld(V0, v0)).halt());
+    run_program_on(cpu, &program).register(VF)
+}
+
+#[test]
+fn the_flag_wins_when_vf_is_the_destination() {
+    // 200 + 100 = 44, carry
+    assert_eq!(vf_after(|asm| asm.add_xy(VF, V0), 200, 100), 1);
+    // 3 - 5 = 254, borrow
+    assert_eq!(vf_after(|asm| asm.sub_xy(VF, V0), 3, 5), 0);
+    // 3 - 5 = 254 again, the other way round
+    assert_eq!(vf_after(|asm| asm.subn_xy(VF, V0), 5, 3), 0);
+    // 20 * 13 = 4, overflow
+    assert_eq!(vf_after(|asm| asm.mul_xy(VF, V0), 20, 13), 1);
+    // 2 >> 1 = 1, nothing shifted out
+    assert_eq!(vf_after(|asm| asm.shr_xy(VF, V0), 0, 0x02), 0);
+    // 0x40 << 1 = 0x80, nothing shifted out
+    assert_eq!(vf_after(|asm| asm.shl_xy(VF, V0), 0, 0x40), 0);
+    // div has no flag, the quotient lands in VF and the remainder in V0
+    assert_eq!(vf_after(|asm| asm.div_xy(VF, V0), 17, 5), 3);
+}
+
+#[test]
+fn ld_i() {
+    let cpu = run_program(&assemble(Assembler::new().ld_i(0x20A).halt()));
+
+    assert_eq!(cpu.index(), 0x20A);
+}
+
+#[test]
+fn ld_i_long_under_xo_chip() {
+    let cpu = run_program_on(
+        CPU::with_quirks(Profile::XoChip.quirks()),
+        &assemble(Assembler::new().ld_i_long(0x1234).ld(V0, 1).halt()),
+    );
+
+    assert_eq!(cpu.index(), 0x1234);
+    assert_eq!(cpu.register(V0), 1);
+}
+
+#[test]
+fn plane_selects_the_drawn_plane() {
+    let cpu = run_program_on(
+        CPU::with_quirks(Profile::XoChip.quirks()),
+        &assemble(Assembler::new().plane(2).ld_f(V0).draw(V0, V0, 5).halt()),
+    );
+
+    assert!(cpu.plane_pixel(1, 0, 0));
+    assert!(!cpu.plane_pixel(0, 0, 0));
+    assert_eq!(cpu.pixel_color(0, 0), 2);
+}
+
+#[test]
+fn jmp_v0_adds_v0() {
+    // 0x200 LD V0, 2; 0x202 JP V0, 0x204; 0x204 LD V1, 1; 0x206 LD V2, 1
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld(V0, 2)
+            .jmp_v0(0x204)
+            .ld(V1, 1)
+            .ld(V2, 1)
+            .halt(),
+    ));
+
+    assert_eq!(cpu.register(V1), 0);
+    assert_eq!(cpu.register(V2), 1);
+}
+
+#[test]
+fn rnd_is_masked_by_kk() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld(V1, 0xFF)
+            .rnd(V0, 0x0F)
+            .rnd(V1, 0)
+            .halt(),
+    ));
+
+    assert_eq!(cpu.register(V0) & 0xF0, 0);
+    assert_eq!(cpu.register(V1), 0);
+}
+
+// hands out `bytes` in order, then starts over
+struct FixedRng {
+    bytes: Vec<u8>,
+    next: usize,
+}
+
+impl RandomSource for FixedRng {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.bytes[self.next % self.bytes.len()];
+        self.next += 1;
+        byte
+    }
+}
+
+#[test]
+fn rnd_uses_the_injected_rng() {
+    let rng = FixedRng {
+        bytes: vec![0xAB, 0x3C, 0xFF],
+        next: 0,
+    };
+
+    let cpu = run_program_on(
+        CPU::new_with_rng(Box::new(rng)),
+        &assemble(
+            Assembler::new()
+                .rnd(V0, 0xFF)
+                .rnd(V1, 0x0F)
+                .rnd(V2, 0x81)
+                .rnd(V3, 0xFF)
+                .halt(),
+        ),
+    );
+
+    assert_eq!(cpu.register(V0), 0xAB);
+    assert_eq!(cpu.register(V1), 0x0C);
+    assert_eq!(cpu.register(V2), 0x81);
+    assert_eq!(cpu.register(V3), 0xAB);
+}
+
+// the V0 after each of 100 Cxkk in a loop
+fn seeded_draws(seed: u64) -> Vec<u8> {
+    let program = assemble(Assembler::new().rnd(V0, 0xFF).jmp(PROGRAM_START as u16));
+    let mut cpu = CPU::new_with_seed(seed);
+    cpu.load_program(&program, PROGRAM_START).unwrap();
+    assert_eq!(cpu.seed(), Some(seed));
+
+    (0..100)
+        .map(|_| {
+            cpu.run_with_limit(2).unwrap();
+            cpu.register(V0)
+        })
+        .collect()
+}
+
+#[test]
+fn the_same_seed_draws_the_same_bytes() {
+    let draws = seeded_draws(0xC8);
+
+    assert_eq!(draws, seeded_draws(0xC8));
+    assert_ne!(draws, seeded_draws(0xC9));
+    assert!(draws.iter().any(|&byte| byte != draws[0]));
+}
+
+#[test]
+fn draw_xors_and_reports_collisions() {
+    let cpu = run_program(&assemble(Assembler::new().ld_f(V0).draw(V0, V0, 5).halt()));
+    assert!(cpu.pixel(0, 0));
+    assert_eq!(cpu.register(VF), 0);
+
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld_f(V0)
+            .draw(V0, V0, 5)
+            .draw(V0, V0, 5)
+            .halt(),
+    ));
+    assert_eq!(lit_pixels(&cpu), 0);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn skip_if_key_and_skip_if_not_key() {
+    let program = assemble(
+        Assembler::new()
+            .ld(V0, 5)
+            .skip_if_key(V0)
+            .ld(V1, 1)
+            .skip_if_not_key(V0)
+            .ld(V2, 1)
+            .halt(),
+    );
+
+    let mut cpu = CPU::new();
+    cpu.set_key(5, true);
+    let cpu = run_program_on(cpu, &program);
+    assert_eq!(cpu.register(V1), 0);
+    assert_eq!(cpu.register(V2), 1);
+
+    let cpu = run_program(&program);
+    assert_eq!(cpu.register(V1), 1);
+    assert_eq!(cpu.register(V2), 0);
+}
+
+#[test]
+fn delay_timer_round_trip() {
+    let cpu = run_program(&assemble(
+        Assembler::new().ld(V0, 9).ld_dt_vx(V0).ld_vx_dt(V1).halt(),
+    ));
+
+    assert_eq!(cpu.delay_timer(), 9);
+    assert_eq!(cpu.register(V1), 9);
+}
+
+#[test]
+fn tick_timers_counts_the_delay_timer_down() {
+    let program = assemble(Assembler::new().ld(V0, 9).ld_dt_vx(V0).ld_vx_dt(V1).halt());
+    let mut cpu = CPU::boot(&program).unwrap();
+
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    cpu.tick_timers();
+    cpu.tick_timers();
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.register(V1), 7);
+}
+
+#[test]
+fn tick_timers_stops_at_zero() {
+    let mut cpu = CPU::boot(&assemble(Assembler::new().ld(V0, 1).ld_dt_vx(V0))).unwrap();
+    cpu.run_with_limit(2).unwrap();
+
+    cpu.tick_timers();
+    cpu.tick_timers();
+
+    assert_eq!(cpu.delay_timer(), 0);
+    assert_eq!(cpu.sound_timer(), 0);
+}
+
+#[test]
+fn ld_st_vx_sets_the_sound_timer() {
+    let cpu = run_program(&assemble(Assembler::new().ld(V0, 7).ld_st_vx(V0).halt()));
+
+    assert_eq!(cpu.sound_timer(), 7);
+}
+
+#[test]
+fn read_key_stalls_until_a_key_is_released() {
+    let mut cpu = CPU::boot(&assemble(Assembler::new().read_key(V3))).unwrap();
+    cpu.set_blocking_key_wait(false);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), PROGRAM_START);
+
+    cpu.set_key(7, true);
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), PROGRAM_START);
+
+    cpu.set_key(7, false);
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), PROGRAM_START + 2);
+    assert_eq!(cpu.register(V3), 7);
+}
+
+#[test]
+fn add_i_vx() {
+    let cpu = run_program(&assemble(
+        Assembler::new().ld_i(0x300).ld(V0, 5).add_i_vx(V0).halt(),
+    ));
+
+    assert_eq!(cpu.index(), 0x305);
+}
+
+fn add_i_past_0xfff(index_overflow_flag: bool) -> CPU {
+    let quirks = Quirks {
+        index_overflow_flag,
+        ..Quirks::default()
+    };
+
+    run_program_on(
+        CPU::with_quirks(quirks),
+        &assemble(
+            Assembler::new()
+                .ld(VF, 7)
+                .ld_i(0xFFF)
+                .ld(V0, 2)
+                .add_i_vx(V0)
+                .halt(),
+        ),
+    )
+}
+
+#[test]
+fn add_i_vx_flags_overflow_when_asked_to() {
+    let cpu = add_i_past_0xfff(true);
+
+    assert_eq!(cpu.index(), 0x1001);
+    assert_eq!(cpu.register(VF), 1);
+}
+
+#[test]
+fn add_i_vx_leaves_vf_alone_by_default() {
+    let cpu = add_i_past_0xfff(false);
+
+    assert_eq!(cpu.index(), 0x1001);
+    assert_eq!(cpu.register(VF), 7);
+}
+
+#[test]
+fn i_wraps_at_0xfff_only_when_masked() {
+    let program = assemble(Assembler::new().ld_i(0xFFF).ld(V0, 3).add_i_vx(V0).halt());
+
+    let vip = run_program_on(CPU::with_quirks(Profile::CosmacVip.quirks()), &program);
+    assert_eq!(vip.index(), 0x002);
+
+    let modern = run_program_on(CPU::with_quirks(Profile::Modern.quirks()), &program);
+    assert_eq!(modern.index(), 0x1002);
+}
+
+#[test]
+fn fx55_past_0xfff_wraps_i_only_when_masked() {
+    let program = assemble(Assembler::new().ld_i(0xFFE).store_regs(V2).halt());
+
+    let vip = run_program_on(CPU::with_quirks(Profile::CosmacVip.quirks()), &program);
+    assert_eq!(vip.index(), 0x001);
+
+    let modern = run_program_on(CPU::with_quirks(Profile::Modern.quirks()), &program);
+    assert_eq!(modern.index(), 0x1001);
+}
+
+#[test]
+fn ld_f_points_at_the_small_font() {
+    let cpu = run_program(&assemble(Assembler::new().ld(V0, 0xA).ld_f(V0).halt()));
+
+    assert_eq!(cpu.index() as usize, FONT_START + 0xA * 5);
+}
+
+#[test]
+fn the_small_font_sits_at_font_start() {
+    let cpu = CPU::new();
+    let font = &cpu.memory()[FONT_START..FONT_START + 80];
+
+    // 0, A and F
+    assert_eq!(&font[..5], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+    assert_eq!(&font[0xA * 5..0xA * 5 + 5], [0xF0, 0x90, 0xF0, 0x90, 0x90]);
+    assert_eq!(&font[75..], [0xF0, 0x80, 0xF0, 0x80, 0x80]);
+}
+
+#[test]
+fn ld_hf_points_at_the_large_font() {
+    let cpu = run_program(&assemble(Assembler::new().ld(V0, 7).ld_hf(V0).halt()));
+
+    assert_eq!(cpu.index() as usize, FONT_START + 80 + 7 * 10);
+}
+
+#[test]
+fn ld_hf_past_9_points_at_the_9() {
+    let cpu = run_program(&assemble(Assembler::new().ld(V0, 0xF).ld_hf(V0).halt()));
+
+    assert_eq!(cpu.index() as usize, FONT_START + 80 + 9 * 10);
+}
+
+#[test]
+fn the_large_font_follows_the_small_one() {
+    let cpu = CPU::new();
+    let large = &cpu.memory()[FONT_START + 80..FONT_START + 180];
+
+    // 0 and the last row of 9
+    assert_eq!(
+        &large[..10],
+        [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C]
+    );
+    assert_eq!(large[99], 0x7C);
+}
+
+#[test]
+fn bcd() {
+    let cpu = run_program(&assemble(
+        Assembler::new().ld(V0, 156).ld_i(0x300).bcd(V0).halt(),
+    ));
+
+    assert_eq!(&cpu.memory()[0x300..0x303], &[1, 5, 6]);
+}
+
+#[test]
+fn bcd_of_0_and_255() {
+    let mut cpu = CPU::new();
+    // so the zeros written for V0 = 0 show up
+    for addr in 0x300..0x303 {
+        cpu.write_memory(addr, 0xFF).unwrap();
+    }
+
+    let cpu = run_program_on(
+        cpu,
+        &assemble(
+            Assembler::new()
+                .ld_i(0x300)
+                .bcd(V0)
+                .ld(V0, 255)
+                .ld_i(0x303)
+                .bcd(V0)
+                .halt(),
+        ),
+    );
+
+    assert_eq!(&cpu.memory()[0x300..0x306], &[0, 0, 0, 2, 5, 5]);
+}
+
+#[test]
+fn store_and_load_regs() {
+    let cpu = run_program(&assemble(
+        Assembler::new()
+            .ld(V0, 0x11)
+            .ld(V1, 0x22)
+            .ld(V2, 0x33)
+            .ld_i(0x300)
+            .store_regs(V2)
+            .ld_i(0x301)
+            .load_regs(V1)
+            .halt(),
+    ));
+
+    assert_eq!(&cpu.memory()[0x300..0x303], &[0x11, 0x22, 0x33]);
+    assert_eq!(cpu.register(V0), 0x22);
+    assert_eq!(cpu.register(V1), 0x33);
+    // the modern profile leaves I past the last register touched
+    assert_eq!(cpu.index(), 0x303);
+}
+
+#[test]
+fn store_and_load_regs_leave_i_alone_without_the_quirk() {
+    let quirks = Quirks {
+        load_store_quirk: false,
+        ..Quirks::default()
+    };
+    let regs = [V0, V1, V2, V3, V4, V5];
+
+    let mut asm = Assembler::new().ld_i(0x300);
+    for (i, &x) in regs.iter().enumerate() {
+        asm = asm.ld(x, 0x10 + i as u8);
+    }
+    asm = asm.store_regs(V5);
+    for &x in &regs {
+        asm = asm.ld(x, 0);
+    }
+    let cpu = run_program_on(
+        CPU::with_quirks(quirks),
+        &assemble(asm.load_regs(V5).halt()),
+    );
+
+    for (i, &x) in regs.iter().enumerate() {
+        assert_eq!(cpu.register(x), 0x10 + i as u8);
+    }
+    assert_eq!(cpu.index(), 0x300);
+}
+
+#[test]
+fn unknown_opcodes_trap() {
+    let mut cpu = CPU::boot(&[0x50, 0x01]).unwrap();
+
+    assert_eq!(cpu.step(), Err(CpuError::UnknownOpcode(0x5001)));
+}
+
+#[test]
+#[should_panic(expected = "program failed at")]
+fn the_harness_panics_on_errors() {
+    run_program(&[0x50, 0x01]);
+}
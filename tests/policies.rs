@@ -0,0 +1,196 @@
+use clayton_cpu::Register::*;
+use clayton_cpu::{Assembler, CpuError, HaltPolicy, OpcodeMask, UnknownPolicy, CPU, PROGRAM_START};
+
+fn div_program() -> Vec<u8> {
+    Assembler::new()
+        .ld(V0, 17)
+        .ld(V1, 5)
+        .div_xy(V0, V1)
+        .halt()
+        .assemble()
+        .unwrap()
+}
+
+fn extended_cpu(program: &[u8]) -> CPU {
+    let mut cpu = CPU::boot(program).unwrap();
+    cpu.set_standard_mode(false);
+    cpu
+}
+
+#[test]
+fn forbidden_div_is_refused() {
+    let mut cpu = extended_cpu(&div_program());
+    cpu.set_allowed_opcodes(OpcodeMask::allow_all().forbid_pattern(0xF00F, 0x800D));
+
+    assert_eq!(
+        cpu.run_with_limit(10),
+        Err(CpuError::ForbiddenOpcode(0x801D))
+    );
+    // refused before it ran
+    assert_eq!(cpu.pc(), PROGRAM_START + 4);
+    assert_eq!(cpu.register(V0), 17);
+}
+
+#[test]
+fn div_runs_without_the_restriction() {
+    let mut cpu = extended_cpu(&div_program());
+
+    assert_eq!(cpu.run_with_limit(3), Ok(3));
+    assert_eq!(cpu.register(V0), 3);
+    assert_eq!(cpu.register(V1), 2);
+}
+
+#[test]
+fn opcode_classes() {
+    let mask = OpcodeMask::only_classes(&[0x6, 0x8]);
+    assert!(mask.allows(0x6005));
+    assert!(mask.allows(0x801D));
+    assert!(!mask.allows(0xF055));
+
+    let mask = OpcodeMask::allow_all().forbid_class(0xF);
+    assert!(!mask.allows(0xF055));
+    assert!(mask.allows(0x1200));
+}
+
+#[test]
+fn standard_mode_rejects_mul_and_div() {
+    let mut cpu = CPU::boot(&div_program()).unwrap();
+
+    assert_eq!(cpu.run_with_limit(10), Err(CpuError::UnknownOpcode(0x801D)));
+    assert_eq!(cpu.register(V0), 17);
+}
+
+#[test]
+fn extended_mode_runs_mul_and_div() {
+    let program = Assembler::new()
+        .ld(V0, 6)
+        .ld(V1, 7)
+        .mul_xy(V0, V1)
+        .ld(V2, 5)
+        .div_xy(V0, V2)
+        .assemble()
+        .unwrap();
+    let mut cpu = extended_cpu(&program);
+
+    assert_eq!(cpu.run_with_limit(5), Ok(5));
+    assert_eq!(cpu.register(V0), 42 / 5);
+    assert_eq!(cpu.register(V2), 42 % 5);
+}
+
+#[test]
+fn cpu_validate_follows_standard_mode() {
+    let program = div_program();
+    let mut cpu = CPU::new();
+
+    assert_eq!(cpu.validate(&program), [(4, 0x801D)]);
+    cpu.set_standard_mode(false);
+    assert_eq!(cpu.validate(&program), []);
+    // the free function only knows about the decoder
+    assert_eq!(clayton_cpu::validate(&program), []);
+}
+
+#[test]
+fn cpu_validate_follows_the_opcode_mask() {
+    let mut cpu = CPU::new();
+    cpu.set_standard_mode(false);
+    cpu.set_allowed_opcodes(OpcodeMask::allow_all().forbid_class(0x6));
+
+    assert_eq!(cpu.validate(&div_program()), [(0, 0x6011), (2, 0x6105)]);
+}
+
+// two loads and a zero word, memory past it is zero too
+fn ends_in_zero(policy: HaltPolicy) -> CPU {
+    let program = Assembler::new()
+        .ld(V0, 1)
+        .ld(V1, 2)
+        .halt()
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.set_zero_opcode_policy(policy);
+    cpu
+}
+
+#[test]
+fn the_zero_word_is_a_nop_by_default() {
+    assert_eq!(HaltPolicy::default(), HaltPolicy::NoOp);
+    let mut cpu = ends_in_zero(HaltPolicy::default());
+
+    assert_eq!(cpu.run_with_limit(5), Ok(5));
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.pc(), PROGRAM_START + 10);
+}
+
+#[test]
+fn the_zero_word_halts_under_halt() {
+    let mut cpu = ends_in_zero(HaltPolicy::Halt);
+
+    assert_eq!(cpu.run_with_limit(5), Ok(3));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.pc(), PROGRAM_START + 6);
+}
+
+#[test]
+fn the_zero_word_errors_under_error() {
+    let mut cpu = ends_in_zero(HaltPolicy::Error);
+
+    assert_eq!(cpu.run_with_limit(5), Err(CpuError::UnknownOpcode(0x0000)));
+    assert_eq!(cpu.register(V1), 2);
+}
+
+#[test]
+fn request_halt_stops_before_the_next_step() {
+    let mut cpu = ends_in_zero(HaltPolicy::NoOp);
+    cpu.step().unwrap();
+
+    cpu.request_halt();
+    assert_eq!(cpu.run_with_limit(5), Ok(0));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.pc(), PROGRAM_START + 2);
+
+    // the request is used up, the next run goes on
+    assert_eq!(cpu.run_with_limit(2), Ok(2));
+    assert_eq!(cpu.register(V1), 2);
+}
+
+// 5001 between two loads
+fn with_unknown(policy: UnknownPolicy) -> CPU {
+    let program = Assembler::new()
+        .ld(V0, 1)
+        .db(&[0x50, 0x01])
+        .ld(V1, 2)
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.set_unknown_opcode_policy(policy);
+    cpu
+}
+
+#[test]
+fn unknown_opcodes_trap_by_default() {
+    assert_eq!(UnknownPolicy::default(), UnknownPolicy::Trap);
+    let mut cpu = with_unknown(UnknownPolicy::Trap);
+
+    assert_eq!(cpu.run_with_limit(5), Err(CpuError::UnknownOpcode(0x5001)));
+    assert_eq!(cpu.register(V0), 1);
+    assert_eq!(cpu.register(V1), 0);
+}
+
+#[test]
+fn unknown_opcodes_are_skipped_under_skip() {
+    let mut cpu = with_unknown(UnknownPolicy::Skip);
+
+    assert_eq!(cpu.run_with_limit(3), Ok(3));
+    assert_eq!(cpu.register(V1), 2);
+    assert!(!cpu.is_halted());
+}
+
+#[test]
+fn unknown_opcodes_halt_under_halt() {
+    let mut cpu = with_unknown(UnknownPolicy::Halt);
+
+    assert_eq!(cpu.run_with_limit(5), Ok(2));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.pc(), PROGRAM_START + 4);
+    assert_eq!(cpu.register(V1), 0);
+}
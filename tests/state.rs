@@ -0,0 +1,245 @@
+use clayton_cpu::Register::*;
+use clayton_cpu::{Assembler, CpuError, Register, StateDiff, CPU, FONT_START, PROGRAM_START};
+
+// touches registers, I, the stack, both timers, memory and the display
+fn busy_program() -> Vec<u8> {
+    Assembler::new()
+        .ld(V0, 7)
+        .ld_dt_vx(V0)
+        .ld_st_vx(V0)
+        .ld_f(V0)
+        .draw(V0, V0, 5)
+        .ld_i(0x300)
+        .store_regs(V1)
+        .call_label("sub")
+        .label("sub")
+        .add_xy(V1, V0)
+        .jmp_label("sub")
+        .assemble()
+        .unwrap()
+}
+
+fn run_steps(steps: usize) -> CPU {
+    let mut cpu = CPU::boot(&busy_program()).unwrap();
+    cpu.run_with_limit(steps).unwrap();
+    cpu
+}
+
+#[test]
+fn reset_reruns_the_rom_from_scratch() {
+    let mut cpu = run_steps(9);
+
+    cpu.reset();
+    assert_eq!(cpu.pc(), PROGRAM_START);
+    assert_eq!(cpu.register(V0), 0);
+    assert!(cpu.call_stack().is_empty());
+    assert!(!cpu.pixel(7, 7));
+
+    cpu.run_with_limit(9).unwrap();
+    assert_eq!(run_steps(9).diff(&cpu), []);
+}
+
+#[test]
+fn reset_and_clear_wipes_the_rom() {
+    let mut cpu = run_steps(9);
+
+    cpu.reset_and_clear();
+
+    assert!(cpu.memory()[PROGRAM_START..].iter().all(|&byte| byte == 0));
+    assert_eq!(cpu.memory()[FONT_START], 0xF0);
+    assert_eq!(cpu.pc(), PROGRAM_START);
+}
+
+#[test]
+fn diff_reports_a_single_register() {
+    let golden = run_steps(9);
+    let mut cpu = run_steps(9);
+    assert_eq!(golden.diff(&cpu), []);
+
+    cpu.set_register(V3, 0x42);
+
+    assert_eq!(
+        golden.diff(&cpu),
+        [StateDiff::Register {
+            index: 3,
+            old: 0x00,
+            new: 0x42
+        }]
+    );
+}
+
+#[test]
+fn poke_and_peek() {
+    let mut cpu = CPU::new();
+
+    cpu.write_memory(0x345, 0x7E).unwrap();
+    assert_eq!(cpu.read_memory(0x345), Ok(0x7E));
+    assert_eq!(cpu.memory()[0x345], 0x7E);
+
+    assert_eq!(
+        cpu.write_memory(0x1000, 0x01),
+        Err(CpuError::AddressOutOfBounds(0x1000))
+    );
+    assert_eq!(
+        cpu.read_memory(usize::MAX),
+        Err(CpuError::AddressOutOfBounds(usize::MAX))
+    );
+}
+
+#[test]
+fn opcodes_see_poked_state() {
+    let program = Assembler::new()
+        .ld(V1, 0x0A)
+        .add_xy(V0, V1)
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+
+    cpu.set_register(V0, 0x30);
+    // turns LD V1, 0x0a into LD V1, 0x0b
+    cpu.write_memory(PROGRAM_START + 1, 0x0B).unwrap();
+    cpu.run_with_limit(2).unwrap();
+
+    assert_eq!(cpu.register(V0), 0x3B);
+}
+
+#[test]
+fn registers_from_nibbles() {
+    assert_eq!(Register::try_from(0x0), Ok(V0));
+    assert_eq!(Register::try_from(0xF), Ok(VF));
+    assert_eq!(Register::try_from(16), Err(CpuError::InvalidRegister(16)));
+    assert_eq!(
+        Register::try_from(0xFF),
+        Err(CpuError::InvalidRegister(0xFF))
+    );
+    assert_eq!(VA.to_string(), "VA");
+}
+
+#[test]
+fn dump_registers_format() {
+    let mut cpu = CPU::boot(&Assembler::new().ld_i(0x2AB).assemble().unwrap()).unwrap();
+    cpu.step().unwrap();
+    cpu.set_register(V0, 0x05);
+    cpu.set_register(VA, 0xFF);
+
+    assert_eq!(
+        cpu.dump_registers(),
+        "V0=05 V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00\n\
+         V8=00 V9=00 VA=ff VB=00 VC=00 VD=00 VE=00 VF=00\n\
+         I=02ab PC=0202 SP=00 DT=00 ST=00"
+    );
+}
+
+#[test]
+fn display_is_one_line() {
+    let program = Assembler::new()
+        .ld(V0, 0x05)
+        .ld(V1, 0x0A)
+        .ld_i(0x300)
+        .call(0x300)
+        .assemble()
+        .unwrap();
+    let mut cpu = CPU::boot(&program).unwrap();
+    cpu.run_with_limit(3).unwrap();
+
+    assert_eq!(
+        cpu.to_string(),
+        "PC=0x206 I=0x300 SP=0 V0=05 V1=0A V2=00 V3=00 V4=00 V5=00 V6=00 V7=00 \
+         V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00 DT=0 ST=0"
+    );
+
+    cpu.step().unwrap();
+    assert!(cpu.to_string().starts_with("PC=0x300 I=0x300 SP=1 "));
+}
+
+#[test]
+fn dump_memory_format() {
+    let mut cpu = CPU::new();
+    for (offset, &byte) in b"Hi!\x7f".iter().enumerate() {
+        cpu.write_memory(0x300 + offset, byte).unwrap();
+    }
+
+    assert_eq!(
+        cpu.dump_memory(0x300, 20),
+        "0300: 48 69 21 7f 00 00 00 00 00 00 00 00 00 00 00 00 |Hi!.............|\n\
+         0310: 00 00 00 00                                     |....|\n"
+    );
+}
+
+#[test]
+fn dump_memory_clamps_to_memory() {
+    let cpu = CPU::new();
+
+    assert_eq!(cpu.dump_memory(0xFFC, 100).lines().count(), 1);
+    assert!(cpu
+        .dump_memory(0xFFC, 100)
+        .starts_with("0ffc: 00 00 00 00 "));
+    assert_eq!(cpu.dump_memory(0x2000, 16), "");
+    assert_eq!(
+        cpu.dump_memory(0x200, usize::MAX).lines().count(),
+        0xE00 / 16
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn save_state_round_trip() {
+    let mut cpu = run_steps(9);
+    let saved = cpu.save_state();
+
+    cpu.run_with_limit(20).unwrap();
+    cpu.set_register(V5, 0xAA);
+    cpu.write_memory(0x400, 0xBB).unwrap();
+    cpu.tick_timers();
+    assert!(!run_steps(9).diff(&cpu).is_empty());
+
+    cpu.load_state(&saved).unwrap();
+
+    assert_eq!(run_steps(9).diff(&cpu), []);
+    assert_eq!(cpu.save_state(), saved);
+    assert_eq!(cpu.call_stack().len(), 1);
+    assert!(cpu.pixel(7, 7));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn load_state_refuses_garbage() {
+    let mut cpu = CPU::new();
+
+    assert_eq!(cpu.load_state(&[1, 2, 3]), Err(CpuError::InvalidSaveState));
+}
+
+// bincode lays the state out in field order with fixed-width integers: the 16
+// registers, PC as a u64, then memory and the stack, each behind a u64 length
+#[cfg(feature = "std")]
+const PC_OFFSET: usize = 16;
+#[cfg(feature = "std")]
+const STACK_OFFSET: usize = PC_OFFSET + 8 + 8 + 0x1000 + 8;
+
+#[cfg(feature = "std")]
+#[test]
+fn load_state_refuses_a_pc_past_memory() {
+    let mut cpu = CPU::new();
+    let mut saved = cpu.save_state();
+    saved[PC_OFFSET..PC_OFFSET + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+    assert_eq!(cpu.load_state(&saved), Err(CpuError::InvalidSaveState));
+    assert_eq!(cpu.pc(), PROGRAM_START);
+
+    saved[PC_OFFSET..PC_OFFSET + 8].copy_from_slice(&0x1000u64.to_le_bytes());
+    assert_eq!(cpu.load_state(&saved), Err(CpuError::InvalidSaveState));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn load_state_refuses_a_return_address_past_memory() {
+    let mut cpu = CPU::new();
+    let mut saved = cpu.save_state();
+    saved[STACK_OFFSET..STACK_OFFSET + 2].copy_from_slice(&0x1000u16.to_le_bytes());
+
+    assert_eq!(cpu.load_state(&saved), Err(CpuError::InvalidSaveState));
+
+    // the same bytes with a reachable address load fine
+    saved[STACK_OFFSET..STACK_OFFSET + 2].copy_from_slice(&0x0FFEu16.to_le_bytes());
+    assert_eq!(cpu.load_state(&saved), Ok(()));
+}
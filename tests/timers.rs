@@ -0,0 +1,127 @@
+use clayton_cpu::Register::*;
+use clayton_cpu::{Assembler, AudioSink, Clock, HaltPolicy, CPU};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+// records every set_playing call
+#[derive(Clone, Default)]
+struct MockSink(Rc<RefCell<Vec<bool>>>);
+
+impl AudioSink for MockSink {
+    fn set_playing(&mut self, playing: bool) {
+        self.0.borrow_mut().push(playing);
+    }
+}
+
+#[test]
+fn the_sink_follows_the_sound_timer() {
+    let sink = MockSink::default();
+    let program = Assembler::new().ld(V0, 3).ld_st_vx(V0).assemble().unwrap();
+    let mut cpu = CPU::boot(&program)
+        .unwrap()
+        .with_audio_sink(Box::new(sink.clone()));
+
+    cpu.run_with_limit(2).unwrap();
+    assert_eq!(*sink.0.borrow(), [true]);
+
+    cpu.tick_timers();
+    cpu.tick_timers();
+    assert_eq!(cpu.sound_timer(), 1);
+    assert_eq!(*sink.0.borrow(), [true]);
+
+    cpu.tick_timers();
+    assert_eq!(cpu.sound_timer(), 0);
+    assert_eq!(*sink.0.borrow(), [true, false]);
+
+    // nothing more to turn off
+    cpu.tick_timers();
+    assert_eq!(*sink.0.borrow(), [true, false]);
+}
+
+// time only moves when the driver sleeps
+#[derive(Default)]
+struct FakeClock {
+    now: Duration,
+}
+
+impl Clock for FakeClock {
+    fn elapsed(&self) -> Duration {
+        self.now
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+}
+
+// runs `steps` instructions in real time and returns how often the delay timer
+// ticked meanwhile, along with the time it took
+fn ticks_during(ips: u32, steps: usize) -> (u8, Duration) {
+    let mut asm = Assembler::new().ld(V0, 255).ld_dt_vx(V0);
+    for _ in 0..steps - 3 {
+        asm = asm.nop();
+    }
+    let mut cpu = CPU::boot(&asm.halt().assemble().unwrap()).unwrap();
+    cpu.set_zero_opcode_policy(HaltPolicy::Halt);
+    let mut clock = FakeClock::default();
+
+    cpu.run_realtime_with_clock(ips, &mut clock).unwrap();
+
+    assert_eq!(cpu.cycle_count(), steps as u64);
+    (255 - cpu.delay_timer(), clock.now)
+}
+
+#[test]
+fn timers_tick_at_60hz_whatever_the_instruction_rate() {
+    let (ticks, elapsed) = ticks_during(600, 120);
+    assert_eq!(ticks, 12);
+    assert_eq!(elapsed.as_millis(), 200);
+
+    let (ticks, elapsed) = ticks_during(1200, 120);
+    assert_eq!(ticks, 6);
+    assert_eq!(elapsed.as_millis(), 100);
+}
+
+// 6 setup steps, then 200 draws of the 0 glyph at 4 steps each, less the last
+// jump, and the halt: 806 steps in all
+fn draw_loop() -> Vec<u8> {
+    Assembler::new()
+        .ld(V4, 1)
+        .ld(V5, 30)
+        .ld_st_vx(V5)
+        .ld(V6, 120)
+        .ld_dt_vx(V6)
+        .ld_f(V0)
+        .label("loop")
+        .draw(V0, V1, 5)
+        .add_xy(V3, V4)
+        .se(V3, 200)
+        .jmp_label("loop")
+        .halt()
+        .assemble()
+        .unwrap()
+}
+
+#[test]
+fn fast_forward_runs_a_draw_loop_quietly() {
+    let sink = MockSink::default();
+    let mut cpu = CPU::boot(&draw_loop())
+        .unwrap()
+        .with_audio_sink(Box::new(sink.clone()));
+    cpu.set_zero_opcode_policy(HaltPolicy::Halt);
+
+    cpu.fast_forward(2000).unwrap();
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.cycle_count(), 806);
+    // one tick every 10 steps
+    assert_eq!(cpu.delay_timer(), 120 - 80);
+    assert_eq!(cpu.sound_timer(), 0);
+    // the beep started and stopped while the sink was detached
+    assert!(sink.0.borrow().is_empty());
+
+    assert_eq!(cpu.register(V3), 200);
+    // an even number of draws leaves the screen blank
+    assert_eq!(cpu.framebuffer().flatten().filter(|&&on| on).count(), 0);
+}
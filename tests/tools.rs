@@ -0,0 +1,107 @@
+use clayton_cpu::testing::run_program;
+use clayton_cpu::Register::*;
+use clayton_cpu::{disassemble, validate, Assembler, AssemblerError, PROGRAM_START};
+
+// the demo program main runs without a ROM
+const DEMO: [u8; 12] = [
+    0x60, 0x05, 0x61, 0x0A, 0x80, 0x1C, 0x80, 0x1D, 0xF0, 0x0A, 0x00, 0x00,
+];
+
+#[test]
+fn assemble_the_demo() {
+    let program = Assembler::new()
+        .ld(V0, 5)
+        .ld(V1, 10)
+        .mul_xy(V0, V1)
+        .div_xy(V0, V1)
+        .read_key(V0)
+        .halt()
+        .assemble()
+        .unwrap();
+
+    assert_eq!(program, DEMO);
+}
+
+#[test]
+fn disassemble_the_demo() {
+    let lines = disassemble(&DEMO, PROGRAM_START);
+    let text: Vec<(usize, u16, &str)> = lines
+        .iter()
+        .map(|(addr, opcode, text)| (*addr, *opcode, text.as_str()))
+        .collect();
+
+    assert_eq!(
+        text,
+        [
+            (0x200, 0x6005, "LD V0, 0x05"),
+            (0x202, 0x610A, "LD V1, 0x0a"),
+            (0x204, 0x801C, "MUL V0, V1"),
+            (0x206, 0x801D, "DIV V0, V1"),
+            (0x208, 0xF00A, "LD V0, K"),
+            (0x20A, 0x0000, "HALT"),
+        ]
+    );
+}
+
+#[test]
+fn disassemble_unknown_opcodes_as_data_words() {
+    let lines = disassemble(&[0x50, 0x01, 0xE0, 0xFF], 0x300);
+
+    assert_eq!(lines[0], (0x300, 0x5001, "DW 0x5001".to_string()));
+    assert_eq!(lines[1], (0x302, 0xE0FF, "DW 0xe0ff".to_string()));
+}
+
+#[test]
+fn disassemble_takes_the_long_index_operand() {
+    let lines = disassemble(&[0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0], PROGRAM_START);
+
+    assert_eq!(
+        lines,
+        [
+            (0x200, 0xF000, "LD I, 0x1234".to_string()),
+            (0x204, 0x00E0, "CLS".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn disassemble_keeps_a_trailing_byte() {
+    let lines = disassemble(&[0x00, 0xE0, 0xAB], PROGRAM_START);
+
+    assert_eq!(lines[1], (0x202, 0xAB, "DB 0xab".to_string()));
+}
+
+#[test]
+fn validate_skips_the_long_index_operand() {
+    // 5001 would be unknown if it were decoded as an instruction
+    assert_eq!(validate(&[0xF0, 0x00, 0x50, 0x01]), []);
+    assert_eq!(validate(&[0x50, 0x01, 0xF0, 0x00]), [(0, 0x5001)]);
+}
+
+#[test]
+fn labels_past_0xfff_are_refused() {
+    let result = Assembler::at(0xFFE).jmp_label("x").label("x").assemble();
+
+    assert_eq!(
+        result,
+        Err(AssemblerError::LabelOutOfRange("x".into(), 0x1000))
+    );
+}
+
+#[test]
+fn labeled_loop_runs() {
+    let program = Assembler::new()
+        .ld(V0, 0)
+        .ld(V1, 1)
+        .label("loop")
+        .add_xy(V0, V1)
+        .se(V0, 5)
+        .jmp_label("loop")
+        .halt()
+        .assemble()
+        .unwrap();
+
+    // the backward jump resolves to the add right after the two lds
+    assert_eq!(&program[8..10], [0x12, 0x04]);
+    assert_eq!(run_program(&program).register(V0), 5);
+}